@@ -0,0 +1,895 @@
+//! Client for the moyn API, as used by the `moyn` command line tool.
+//!
+//! ```no_run
+//! use moyn::{Config, MoynClient};
+//!
+//! let client = MoynClient::new(Config {
+//!     api_url: "https://moyn.dev".to_string(),
+//!     api_token: "moyn_...".to_string(),
+//!     ..Config::default()
+//! })?;
+//! for post in client.list_posts(None)? {
+//!     println!("{} {}", post.id, post.title);
+//! }
+//! # Ok::<(), moyn::Error>(())
+//! ```
+
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Credentials for one account on one instance
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Config {
+    /// Name of the profile this was loaded from
+    #[serde(skip)]
+    pub profile: String,
+    /// Empty when the token is kept in the system keyring
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_token: String,
+    pub api_url: String,
+    /// Seconds to wait for a connection to the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    /// Seconds a whole request, from connecting to reading the response, may
+    /// take before giving up. Media uploads use `upload_timeout` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<u64>,
+    /// Seconds a whole media upload may take before giving up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_timeout: Option<u64>,
+    /// Proxy for all requests, overriding HTTP_PROXY and HTTPS_PROXY
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// PEM files with CA certificates to trust in addition to the system ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_certs: Vec<PathBuf>,
+    /// How often to retry a request that failed with a network error, 5xx or 429
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+    /// Also retry creating and updating things. Only safe on instances that
+    /// drop repeated requests with the same `Idempotency-Key`; off by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_writes: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Post {
+    pub id: u64,
+    pub title: String,
+    pub slug: String,
+    pub url: String,
+    /// Markdown body; not always included in listings
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub published: Option<bool>,
+    #[serde(default)]
+    pub space: Option<String>,
+    #[serde(default)]
+    pub published_at: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+/// A post as sent when creating or updating it
+#[derive(Serialize, Debug, Clone)]
+pub struct CreatePost {
+    pub title: String,
+    pub content: String,
+    pub published: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Space {
+    pub slug: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub visibility: String,
+    pub access_token: Option<String>,
+    pub url: String,
+    pub token_url: Option<String>,
+    /// When the access token and share URL stop working, if ever
+    #[serde(default)]
+    pub token_expires_at: Option<String>,
+}
+
+/// A space as sent when creating it, or the fields to change when updating it
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CreateSpace {
+    /// Required when creating a space
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+}
+
+/// Someone with access to a space, or an invitation to it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Member {
+    /// Not set for email invitations that haven't been accepted yet
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// `reader`, `writer` or `admin`
+    pub role: String,
+    /// `active`, or `invited` until an invitation is accepted
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// Who to add to a space, by username or email, and with which role
+#[derive(Serialize, Debug, Clone)]
+pub struct Invite {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub role: String,
+}
+
+/// The account a token belongs to
+#[derive(Deserialize, Debug)]
+pub struct Me {
+    pub user: User,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub username: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl User {
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) if !name.is_empty() => format!("{} (@{})", name, self.username),
+            _ => format!("@{}", self.username),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PostResponse {
+    post: Post,
+}
+
+/// The body sent to create or update a post, `{"post": {...}}`
+#[derive(Serialize)]
+pub struct PostRequest<'a> {
+    pub post: &'a CreatePost,
+}
+
+#[derive(Deserialize)]
+struct MediaResponse {
+    media: Media,
+}
+
+#[derive(Deserialize)]
+struct Media {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct SpaceResponse {
+    space: Space,
+}
+
+#[derive(Serialize)]
+struct SpaceRequest<'a> {
+    space: &'a CreateSpace,
+}
+
+#[derive(Serialize)]
+struct TokenRequest<'a> {
+    token: NewToken<'a>,
+}
+
+#[derive(Serialize)]
+struct NewToken<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct MemberResponse {
+    member: Member,
+}
+
+#[derive(Serialize)]
+struct InviteRequest<'a> {
+    member: &'a Invite,
+}
+
+/// Everything that can go wrong, grouped by what the user can do about it.
+/// Each kind maps to the process exit code of the `moyn` binary.
+#[derive(Debug)]
+pub enum Error {
+    /// Missing or unusable configuration: not logged in, unknown profile,
+    /// invalid proxy or CA certificate. Exit code 3.
+    Config(String),
+    /// The token was rejected or lacks permission. Exit code 4.
+    Auth(String),
+    /// The post, space or file doesn't exist, or isn't visible with this token. Exit code 5.
+    NotFound(String),
+    /// The input was rejected, locally or by the server. Exit code 6.
+    Validation(String),
+    /// The server could not be reached. Exit code 7.
+    Network(String),
+    /// The server failed or answered with something unexpected. Exit code 8.
+    Server(String),
+    /// Anything else, such as local file system errors. Exit code 1.
+    Other(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Other(_) => 1,
+            Error::Config(_) => 3,
+            Error::Auth(_) => 4,
+            Error::NotFound(_) => 5,
+            Error::Validation(_) => 6,
+            Error::Network(_) => 7,
+            Error::Server(_) => 8,
+        }
+    }
+
+    /// Prefix the message, keeping the kind of error.
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        let add = |message: String| format!("{}: {}", context, message);
+        match self {
+            Error::Config(m) => Error::Config(add(m)),
+            Error::Auth(m) => Error::Auth(add(m)),
+            Error::NotFound(m) => Error::NotFound(add(m)),
+            Error::Validation(m) => Error::Validation(add(m)),
+            Error::Network(m) => Error::Network(add(m)),
+            Error::Server(m) => Error::Server(add(m)),
+            Error::Other(m) => Error::Other(add(m)),
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Error::Config(m)
+            | Error::Auth(m)
+            | Error::NotFound(m)
+            | Error::Validation(m)
+            | Error::Network(m)
+            | Error::Server(m)
+            | Error::Other(m) => m,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+/// The error bodies the API sends, in any of the shapes it has used
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    /// `{"error": "Title can't be blank"}` or `{"message": "..."}`
+    Message {
+        #[serde(alias = "message")]
+        error: String,
+    },
+    /// `{"errors": {"title": ["can't be blank"]}}`
+    Fields { errors: BTreeMap<String, Vec<String>> },
+    /// `{"errors": ["Title can't be blank"]}`
+    List { errors: Vec<String> },
+}
+
+/// Turn an error response body into a readable message, falling back to the
+/// raw body when it isn't JSON.
+fn error_message(status: StatusCode, body: &str) -> String {
+    let message = match serde_json::from_str(body) {
+        Ok(ErrorBody::Message { error }) => error,
+        Ok(ErrorBody::Fields { errors }) => errors
+            .iter()
+            .flat_map(|(field, messages)| messages.iter().map(move |message| format!("{} {}", field, message)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Ok(ErrorBody::List { errors }) => errors.join(", "),
+        Err(_) => body.trim().to_string(),
+    };
+    if message.is_empty() {
+        status.to_string()
+    } else {
+        format!("{} ({})", message, status)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+const USER_AGENT: &str = concat!("moyn/", env!("CARGO_PKG_VERSION"));
+
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+
+const DEFAULT_READ_TIMEOUT: u64 = 30;
+
+/// Large files on slow links take far longer than other requests
+const DEFAULT_UPLOAD_TIMEOUT: u64 = 600;
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest `Retry-After` we wait for before giving up on a rate-limited request
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// A failed attempt at a request that is about to be retried
+#[derive(Debug, Clone)]
+pub struct Retry {
+    /// Why the attempt failed, e.g. `Server error 503 Service Unavailable`
+    pub reason: String,
+    /// How long the client waits before trying again
+    pub delay: Duration,
+    /// Which attempt failed, starting at 1
+    pub attempt: u32,
+}
+
+/// A client for one account on one instance
+pub struct MoynClient {
+    config: Config,
+    http: reqwest::blocking::Client,
+    on_retry: Option<Box<RetryCallback>>,
+}
+
+type RetryCallback = dyn Fn(&Retry) + Send + Sync;
+
+impl MoynClient {
+    /// Set up a client with the config's timeouts, proxy and CA certificates.
+    pub fn new(config: Config) -> Result<Self> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(Duration::from_secs(config.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT)))
+            .timeout(Duration::from_secs(config.read_timeout.unwrap_or(DEFAULT_READ_TIMEOUT)));
+
+        if let Some(proxy) = &config.proxy {
+            let proxy =
+                reqwest::Proxy::all(proxy).map_err(|e| Error::Config(format!("Invalid proxy '{}': {}", proxy, e)))?;
+            builder = builder.proxy(proxy);
+        }
+
+        for path in &config.ca_certs {
+            let pem = fs::read(path).map_err(|e| {
+                Error::Config(format!("Could not read CA certificate {}: {}", path.display(), e))
+            })?;
+            let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| Error::Config(format!("Invalid CA certificate {}: {}", path.display(), e)))?;
+            builder = builder.tls_certs_merge(certs);
+        }
+
+        let http = builder
+            .build()
+            .map_err(|e| Error::Config(format!("Could not create HTTP client: {}", e)))?;
+        Ok(MoynClient { config, http, on_retry: None })
+    }
+
+    /// Call `callback` before each retry, e.g. to tell the user why a request
+    /// is taking longer. Retries are silent otherwise.
+    pub fn on_retry(mut self, callback: impl Fn(&Retry) + Send + Sync + 'static) -> Self {
+        self.on_retry = Some(Box::new(callback));
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Who the token belongs to, and what it may do.
+    pub fn me(&self) -> Result<Me> {
+        let response = self.send(self.request(Method::GET, "/api/v1/me"), || self.not_an_instance())?;
+        json(response)
+    }
+
+    /// Posts outside any space, or the posts of one space, fetched page by
+    /// page as the iterator is consumed.
+    pub fn posts(&self, space: Option<&str>) -> Paginated<'_, Post> {
+        Paginated::new(self, &posts_path(space), "posts", self.posts_not_found(space))
+    }
+
+    /// All posts outside any space, or all posts of one space.
+    pub fn list_posts(&self, space: Option<&str>) -> Result<Vec<Post>> {
+        self.posts(space).collect()
+    }
+
+    pub fn get_post(&self, id: u64) -> Result<Post> {
+        let request = self.request(Method::GET, &format!("/api/v1/posts/{}", id));
+        let response = self.send(request, || format!("Post {} not found.", id))?;
+        Ok(json::<PostResponse>(response)?.post)
+    }
+
+    pub fn create_post(&self, space: Option<&str>, post: &CreatePost) -> Result<Post> {
+        let request = self
+            .request(Method::POST, &posts_path(space))
+            .header("Idempotency-Key", idempotency_key())
+            .json(&PostRequest { post });
+        let response = self.send(request, || self.posts_not_found(space))?;
+        Ok(json::<PostResponse>(response)?.post)
+    }
+
+    pub fn update_post(&self, id: u64, post: &CreatePost) -> Result<Post> {
+        let request = self
+            .request(Method::PATCH, &format!("/api/v1/posts/{}", id))
+            .header("Idempotency-Key", idempotency_key())
+            .json(&PostRequest { post });
+        let response = self.send(request, || format!("Post {} not found.", id))?;
+        Ok(json::<PostResponse>(response)?.post)
+    }
+
+    pub fn delete_post(&self, id: u64) -> Result<()> {
+        let request = self.request(Method::DELETE, &format!("/api/v1/posts/{}", id));
+        self.send(request, || format!("Post {} not found.", id))?;
+        Ok(())
+    }
+
+    /// Upload an image or other file, returning its URL.
+    pub fn upload_media(&self, path: &Path) -> Result<String> {
+        let form = reqwest::blocking::multipart::Form::new()
+            .file("file", path)
+            .map_err(|e| Error::Other(format!("Could not read {}: {}", path.display(), e)))?;
+
+        let timeout = Duration::from_secs(self.config.upload_timeout.unwrap_or(DEFAULT_UPLOAD_TIMEOUT));
+        let request = self.request(Method::POST, "/api/v1/media").multipart(form).timeout(timeout);
+        let response = self.send(request, || "Media uploads are not supported by this instance.".to_string())?;
+        Ok(json::<MediaResponse>(response)?.media.url)
+    }
+
+    /// Spaces you own or are a member of, fetched page by page as the
+    /// iterator is consumed.
+    pub fn spaces(&self) -> Paginated<'_, Space> {
+        Paginated::new(self, "/api/v1/spaces", "spaces", self.not_an_instance())
+    }
+
+    pub fn list_spaces(&self) -> Result<Vec<Space>> {
+        self.spaces().collect()
+    }
+
+    pub fn create_space(&self, space: &CreateSpace) -> Result<Space> {
+        let request = self
+            .request(Method::POST, "/api/v1/spaces")
+            .header("Idempotency-Key", idempotency_key())
+            .json(&SpaceRequest { space });
+        let response = self.send(request, || self.not_an_instance())?;
+        Ok(json::<SpaceResponse>(response)?.space)
+    }
+
+    pub fn get_space(&self, slug: &str) -> Result<Space> {
+        let request = self.request(Method::GET, &format!("/api/v1/spaces/{}", slug));
+        let response = self.send(request, || space_not_found(slug))?;
+        Ok(json::<SpaceResponse>(response)?.space)
+    }
+
+    /// Change the fields of a space that are set in `changes`. Setting `slug`
+    /// renames the space, which changes its URL.
+    pub fn update_space(&self, slug: &str, changes: &CreateSpace) -> Result<Space> {
+        let request = self
+            .request(Method::PATCH, &format!("/api/v1/spaces/{}", slug))
+            .header("Idempotency-Key", idempotency_key())
+            .json(&SpaceRequest { space: changes });
+        let response = self.send(request, || space_not_found(slug))?;
+        Ok(json::<SpaceResponse>(response)?.space)
+    }
+
+    pub fn delete_space(&self, slug: &str) -> Result<()> {
+        let request = self.request(Method::DELETE, &format!("/api/v1/spaces/{}", slug));
+        self.send(request, || space_not_found(slug))?;
+        Ok(())
+    }
+
+    /// Replace the access token of a space, and with it the share URL. The
+    /// new token stops working at `expires_at` (RFC 3339) if given.
+    pub fn rotate_space_token(&self, slug: &str, expires_at: Option<&str>) -> Result<Space> {
+        let request = self
+            .request(Method::POST, &format!("/api/v1/spaces/{}/token", slug))
+            .header("Idempotency-Key", idempotency_key())
+            .json(&TokenRequest { token: NewToken { expires_at } });
+        let response = self.send(request, || space_not_found(slug))?;
+        Ok(json::<SpaceResponse>(response)?.space)
+    }
+
+    /// Remove the access token of a space, so its share URL stops working.
+    pub fn revoke_space_token(&self, slug: &str) -> Result<()> {
+        let request = self.request(Method::DELETE, &format!("/api/v1/spaces/{}/token", slug));
+        self.send(request, || space_not_found(slug))?;
+        Ok(())
+    }
+
+    /// Members and pending invitations of a space, fetched page by page as
+    /// the iterator is consumed.
+    pub fn members(&self, slug: &str) -> Paginated<'_, Member> {
+        Paginated::new(self, &format!("/api/v1/spaces/{}/members", slug), "members", space_not_found(slug))
+    }
+
+    pub fn list_members(&self, slug: &str) -> Result<Vec<Member>> {
+        self.members(slug).collect()
+    }
+
+    /// Add an existing user to a space, or invite someone by email.
+    pub fn invite_member(&self, slug: &str, invite: &Invite) -> Result<Member> {
+        let request = self
+            .request(Method::POST, &format!("/api/v1/spaces/{}/members", slug))
+            .header("Idempotency-Key", idempotency_key())
+            .json(&InviteRequest { member: invite });
+        let response = self.send(request, || space_not_found(slug))?;
+        Ok(json::<MemberResponse>(response)?.member)
+    }
+
+    /// Remove a member, or withdraw an invitation, by username or email.
+    pub fn remove_member(&self, slug: &str, member: &str) -> Result<()> {
+        let request = self.request(Method::DELETE, &format!("/api/v1/spaces/{}/members/{}", slug, member));
+        self.send(request, || format!("'{}' is not a member of space '{}'.", member, slug))?;
+        Ok(())
+    }
+
+    fn not_an_instance(&self) -> String {
+        format!("{} does not look like a moyn instance.", self.config.api_url)
+    }
+
+    /// The message for a 404 from the posts of a space, or of the profile
+    fn posts_not_found(&self, space: Option<&str>) -> String {
+        match space {
+            Some(space) => space_not_found(space),
+            None => self.not_an_instance(),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.request_url(method, &format!("{}{}", self.config.api_url, path))
+    }
+
+    fn request_url(&self, method: Method, url: &str) -> RequestBuilder {
+        self.http
+            .request(method, url)
+            .header("Authorization", format!("Bearer {}", self.config.api_token))
+    }
+
+    /// Send a request and turn unsuccessful responses into errors, using
+    /// `not_found` for the message of a 404.
+    fn send(&self, request: RequestBuilder, not_found: impl FnOnce() -> String) -> Result<Response> {
+        let response = self
+            .send_with_retries(request)
+            .map_err(|e| Error::Network(format!("Could not reach {}: {}", self.config.api_url, e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status == StatusCode::NOT_FOUND {
+            return Err(Error::NotFound(not_found()));
+        }
+
+        let message = error_message(status, &response.text().unwrap_or_default());
+        Err(match status {
+            StatusCode::UNAUTHORIZED => Error::Auth(format!("The API token was rejected: {}", message)),
+            StatusCode::FORBIDDEN => Error::Auth(format!("The API token is not allowed to do this: {}", message)),
+            StatusCode::BAD_REQUEST | StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY => {
+                Error::Validation(message)
+            }
+            _ => Error::Server(format!("Server error: {}", message)),
+        })
+    }
+
+    /// Send a request, retrying network errors, 5xx responses and 429s with
+    /// exponential backoff. Requests that aren't idempotent may have gone
+    /// through before failing, so they are only retried after a 429, or when
+    /// they carry an `Idempotency-Key` and `retry_writes` says the instance
+    /// uses it to drop duplicates.
+    fn send_with_retries(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let request = request.build()?;
+
+        let method = request.method();
+        let idempotent = method == Method::GET
+            || method == Method::HEAD
+            || method == Method::PUT
+            || method == Method::DELETE
+            || (self.config.retry_writes == Some(true) && request.headers().contains_key("Idempotency-Key"));
+        let max_retries = self.config.max_retries.unwrap_or(DEFAULT_MAX_RETRIES);
+
+        let mut attempt = 0;
+        loop {
+            // The last attempt, and bodies streamed from a file, can't be replayed
+            let Some(retry) = request.try_clone().filter(|_| attempt < max_retries) else {
+                return self.http.execute(request);
+            };
+
+            let result = self.http.execute(retry);
+            let (reason, delay) = match &result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| parse_retry_after(value, chrono::Utc::now()));
+                    ("Rate limited".to_string(), retry_after.unwrap_or_else(|| backoff(attempt)))
+                }
+                Ok(response) if idempotent && response.status().is_server_error() => {
+                    (format!("Server error {}", response.status()), backoff(attempt))
+                }
+                Err(e) if idempotent && (e.is_connect() || e.is_timeout() || e.is_request()) => {
+                    (format!("Request failed ({})", e), backoff(attempt))
+                }
+                _ => return result,
+            };
+            if delay > RETRY_MAX_DELAY {
+                return result;
+            }
+
+            if let Some(on_retry) = &self.on_retry {
+                on_retry(&Retry { reason, delay, attempt: attempt + 1 });
+            }
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+/// Items of a listing, following the pagination the server uses: a `Link`
+/// header with `rel="next"`, a `next_cursor`, or page numbers.
+pub struct Paginated<'a, T> {
+    client: &'a MoynClient,
+    /// The page to fetch once `items` runs out, or why the first one can't be
+    next: Option<Result<Url>>,
+    /// Key of the items in the response, e.g. `posts`
+    key: &'static str,
+    not_found: String,
+    items: std::vec::IntoIter<T>,
+}
+
+/// Where the next page is, under `meta`, `pagination` or at the top level
+#[derive(Deserialize, Default)]
+struct PageInfo {
+    #[serde(default)]
+    next_cursor: Option<String>,
+    #[serde(default)]
+    next_page: Option<u64>,
+    #[serde(default)]
+    page: Option<u64>,
+    #[serde(default)]
+    total_pages: Option<u64>,
+}
+
+impl<'a, T: DeserializeOwned> Paginated<'a, T> {
+    fn new(client: &'a MoynClient, path: &str, key: &'static str, not_found: String) -> Self {
+        // An invalid URL is returned as the first item, so it fails like any other request
+        let url = format!("{}{}", client.config.api_url, path);
+        let next = Url::parse(&url).map_err(|e| Error::Network(format!("Could not reach {}: {}", url, e)));
+        Paginated {
+            client,
+            next: Some(next),
+            key,
+            not_found,
+            items: Vec::new().into_iter(),
+        }
+    }
+
+    /// Fetch the page at `url`, returning its items and the next page.
+    fn fetch(&self, url: &Url) -> Result<(Vec<T>, Option<Url>)> {
+        let request = self.client.request_url(Method::GET, url.as_str());
+        let response = self.client.send(request, || self.not_found.clone())?;
+
+        let link = response
+            .headers()
+            .get_all(reqwest::header::LINK)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(next_link);
+        let mut body: serde_json::Value = json(response)?;
+
+        let items = serde_json::from_value(body.get_mut(self.key).map(serde_json::Value::take).unwrap_or_default())
+            .map_err(|e| Error::Server(format!("Could not parse response: {}", e)))?;
+        let info = ["meta", "pagination"]
+            .iter()
+            .find_map(|key| body.get(key))
+            .unwrap_or(&body);
+        let info: PageInfo = serde_json::from_value(info.clone()).unwrap_or_default();
+
+        let next = if let Some(link) = link {
+            url.join(&link).ok()
+        } else if let Some(cursor) = info.next_cursor {
+            Some(with_query(url, "cursor", &cursor))
+        } else if let Some(page) = info.next_page {
+            Some(with_query(url, "page", &page.to_string()))
+        } else if let (Some(page), Some(total_pages)) = (info.page, info.total_pages)
+            && page < total_pages
+        {
+            Some(with_query(url, "page", &(page + 1).to_string()))
+        } else {
+            None
+        };
+
+        // A server that links a page to itself would otherwise loop forever
+        Ok((items, next.filter(|next| next != url)))
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for Paginated<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Some(Ok(item));
+            }
+            let url = match self.next.take()? {
+                Ok(url) => url,
+                Err(e) => return Some(Err(e)),
+            };
+            match self.fetch(&url) {
+                Ok((items, next)) => {
+                    self.items = items.into_iter();
+                    self.next = next.map(Ok);
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// The `rel="next"` target of a `Link` header, if there is one.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (target, params) = link.split_once(';')?;
+        let is_next = params.split(';').any(|param| {
+            let param = param.trim();
+            param == "rel=\"next\"" || param == "rel=next"
+        });
+        let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+        is_next.then(|| target.to_string())
+    })
+}
+
+/// `url` with the query parameter `key` set to `value`.
+fn with_query(url: &Url, key: &str, value: &str) -> Url {
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().filter(|(k, _)| k != key).collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs).append_pair(key, value);
+    url
+}
+
+fn space_not_found(slug: &str) -> String {
+    format!("Space '{}' not found or you don't have access.", slug)
+}
+
+fn posts_path(space: Option<&str>) -> String {
+    match space {
+        Some(space) => format!("/api/v1/spaces/{}/posts", space),
+        None => "/api/v1/posts".to_string(),
+    }
+}
+
+fn json<T: DeserializeOwned>(response: Response) -> Result<T> {
+    response
+        .json()
+        .map_err(|e| Error::Server(format!("Could not parse response: {}", e)))
+}
+
+/// Exponential backoff with jitter, so that parallel clients spread out.
+fn backoff(attempt: u32) -> Duration {
+    let delay = RETRY_BASE_DELAY * 2u32.saturating_pow(attempt);
+    let jitter = random_u64() % (delay.as_millis() as u64 / 2 + 1);
+    delay / 2 + Duration::from_millis(jitter)
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - now).to_std().unwrap_or_default())
+}
+
+fn random_u64() -> u64 {
+    use std::hash::BuildHasher;
+    std::hash::RandomState::new().hash_one(std::time::SystemTime::now())
+}
+
+/// A fresh key for a write, reused when the request is retried
+fn idempotency_key() -> String {
+    format!("{:016x}{:016x}", random_u64(), random_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_retry_after_accepts_seconds_and_dates() {
+        let now = chrono::DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z").unwrap().with_timezone(&chrono::Utc);
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn backoff_doubles_with_jitter() {
+        for attempt in 0..4 {
+            let full = RETRY_BASE_DELAY * 2u32.pow(attempt);
+            let delay = backoff(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{:?} for attempt {}", delay, attempt);
+        }
+    }
+
+    #[test]
+    fn error_message_reads_json_error_bodies() {
+        let status = StatusCode::UNPROCESSABLE_ENTITY;
+
+        assert_eq!(
+            error_message(status, r#"{"error": "Slug has already been taken"}"#),
+            "Slug has already been taken (422 Unprocessable Entity)"
+        );
+        assert_eq!(
+            error_message(status, r#"{"errors": {"slug": ["is taken"], "title": ["can't be blank"]}}"#),
+            "slug is taken, title can't be blank (422 Unprocessable Entity)"
+        );
+        assert_eq!(error_message(status, r#"{"errors": ["Title can't be blank"]}"#), "Title can't be blank (422 Unprocessable Entity)");
+        assert_eq!(error_message(StatusCode::BAD_GATEWAY, "<html>Bad gateway</html>\n"), "<html>Bad gateway</html> (502 Bad Gateway)");
+        assert_eq!(error_message(StatusCode::BAD_GATEWAY, ""), "502 Bad Gateway");
+    }
+
+    #[test]
+    fn paginated_reports_an_invalid_api_url() {
+        let client = MoynClient::new(Config { api_url: "moyn.dev".to_string(), ..Config::default() }).unwrap();
+        let mut posts = client.posts(None);
+
+        assert!(matches!(posts.next(), Some(Err(Error::Network(_)))));
+        assert!(posts.next().is_none());
+    }
+
+    #[test]
+    fn next_link_finds_the_next_page() {
+        let header = r#"<https://moyn.dev/api/v1/posts?page=1>; rel="prev", <https://moyn.dev/api/v1/posts?page=3>; rel="next""#;
+
+        assert_eq!(next_link(header).as_deref(), Some("https://moyn.dev/api/v1/posts?page=3"));
+        assert_eq!(next_link(r#"</api/v1/posts?page=2>; rel="last""#), None);
+    }
+
+    #[test]
+    fn with_query_replaces_the_parameter() {
+        let url = Url::parse("https://moyn.dev/api/v1/posts?page=2&per_page=50").unwrap();
+
+        assert_eq!(with_query(&url, "page", "3").as_str(), "https://moyn.dev/api/v1/posts?per_page=50&page=3");
+        assert_eq!(with_query(&url, "cursor", "abc").as_str(), "https://moyn.dev/api/v1/posts?page=2&per_page=50&cursor=abc");
+    }
+}
@@ -1,8 +1,15 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use moyn::{Config, CreatePost, CreateSpace, Error, Invite, Me, Member, MoynClient, Post, PostRequest, Space, User};
+use notify::{EventKind, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(
@@ -26,28 +33,90 @@ use std::path::PathBuf;
         Learn more: https://moyn.dev"
 )]
 struct Cli {
+    /// Profile to use instead of the current one
+    #[arg(short, long, global = true, env = "MOYN_PROFILE")]
+    profile: Option<String>,
+    /// Output format for results
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text
+    Table,
+    Json,
+    Yaml,
+    /// Tab-separated values with a header row
+    Tsv,
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    /// Store your API token
-    Login,
+    /// Store your API token (in the profile given with --profile)
+    Login {
+        /// Read the token from stdin without prompting, e.g. in CI
+        #[arg(long)]
+        token_stdin: bool,
+        /// API URL (defaults to https://moyn.dev, prompted for unless --token-stdin is given)
+        #[arg(long)]
+        url: Option<String>,
+        /// Store the token in the config file instead of the system keyring
+        #[arg(long)]
+        no_keyring: bool,
+    },
+    /// Show the current user, instance, profile and token scopes
+    Whoami,
+    /// <COMMAND> - Manage profiles for multiple accounts and instances
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// <TITLE> - Create a draft from a template and open it in $EDITOR
+    New(NewArgs),
     /// <FILE> - Publish a markdown file as a post
-    Publish {
-        /// Path to the markdown file
-        file: PathBuf,
+    Publish(PublishArgs),
+    /// <DIR> - Mirror a directory of markdown files to moyn
+    Sync {
+        /// Directory containing markdown files
+        dir: PathBuf,
+        /// Delete posts whose files were removed from the directory
+        #[arg(long)]
+        delete: bool,
+        /// Print the plan without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        content: ContentArgs,
+    },
+    /// [DIR] - Download your posts as markdown files
+    Pull {
+        /// Directory to write the files to
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Only pull the posts in this space
+        #[arg(long)]
+        space: Option<String>,
+        /// Overwrite files that already exist
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// [--space <SLUG>] [--tag <TAG>] [--published|--drafts] [--since <DATE>] [--until <DATE>] [--sort <ORDER>] - List your posts
+    Posts(PostsArgs),
+    /// <COMMAND> - Inspect a single post
+    Post {
+        #[command(subcommand)]
+        command: PostCommands,
     },
-    /// List your posts
-    Posts,
     /// <ID> - Delete a post by ID
     Delete {
         /// Post ID to delete
         id: u64,
     },
     /// List all spaces you own or are a member of
-    Spaces,
+    Spaces(ListArgs),
     /// <COMMAND> - Manage spaces
     Space {
         #[command(subcommand)]
@@ -55,6 +124,216 @@ enum Commands {
     },
 }
 
+impl Commands {
+    /// The name of the command if it only prints text for people, and so
+    /// can't honour `--output`
+    fn text_only(&self) -> Option<&'static str> {
+        match self {
+            Commands::Login { .. } => Some("login"),
+            Commands::Profile { .. } => Some("profile"),
+            Commands::New(_) => Some("new"),
+            Commands::Publish(args) if args.dry_run => Some("publish --dry-run"),
+            Commands::Sync { .. } => Some("sync"),
+            Commands::Pull { .. } => Some("pull"),
+            Commands::Delete { .. } => Some("delete"),
+            Commands::Space { command } => match command {
+                SpaceCommands::Delete { .. } => Some("space delete"),
+                SpaceCommands::RemoveMember { .. } => Some("space remove-member"),
+                SpaceCommands::Token { command: TokenCommands::Revoke { .. } } => Some("space token revoke"),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Args)]
+struct NewArgs {
+    /// Post title
+    title: String,
+    /// Custom slug (derived from the title if omitted)
+    #[arg(short, long)]
+    slug: Option<String>,
+    /// Comma-separated tags
+    #[arg(short, long, value_delimiter = ',')]
+    tags: Vec<String>,
+    /// Space to publish the post to
+    #[arg(long)]
+    space: Option<String>,
+    /// Template name from the templates directory next to config.json
+    #[arg(long, default_value = "default")]
+    template: String,
+    /// Directory to create the file in
+    #[arg(short, long, default_value = ".")]
+    dir: PathBuf,
+    /// Only create the file, don't open an editor
+    #[arg(long)]
+    no_edit: bool,
+}
+
+#[derive(Args)]
+struct PublishArgs {
+    /// Path to the markdown file (or a directory with --watch)
+    file: PathBuf,
+    /// Always create a new post, even if this file was published before
+    #[arg(long)]
+    new: bool,
+    /// Write the post's id, url and published_at back into the file's frontmatter
+    #[arg(long)]
+    write_back: bool,
+    /// Print the request that would be sent without sending it
+    #[arg(long, conflicts_with = "watch")]
+    dry_run: bool,
+    /// Republish whenever the file, or any markdown file in the directory, is saved
+    #[arg(short, long, conflicts_with = "new")]
+    watch: bool,
+    #[command(flatten)]
+    content: ContentArgs,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// List profiles
+    List,
+    /// <NAME> - Make a profile the current one
+    Use {
+        /// Profile name
+        name: String,
+    },
+    /// <NAME> - Remove a profile and its credentials
+    Remove {
+        /// Profile name
+        name: String,
+    },
+}
+
+/// How much of a listing to fetch
+#[derive(Args, Clone, Copy)]
+struct ListArgs {
+    /// Show at most this many [default: 50 in tables, everything otherwise]
+    #[arg(short = 'n', long, conflicts_with = "all")]
+    limit: Option<usize>,
+    /// Show everything, fetching as many pages as needed
+    #[arg(long)]
+    all: bool,
+}
+
+const DEFAULT_LIST_LIMIT: usize = 50;
+
+impl ListArgs {
+    /// Tables stop at `DEFAULT_LIST_LIMIT` unless told otherwise, while the
+    /// formats meant for scripts get everything so nothing is cut silently.
+    fn limit(self, format: OutputFormat) -> Option<usize> {
+        match (self.all, self.limit, format) {
+            (true, _, _) => None,
+            (false, Some(limit), _) => Some(limit),
+            (false, None, OutputFormat::Table) => Some(DEFAULT_LIST_LIMIT),
+            (false, None, _) => None,
+        }
+    }
+}
+
+#[derive(Args)]
+struct PostsArgs {
+    /// Only list the posts in this space
+    #[arg(short, long)]
+    space: Option<String>,
+    #[command(flatten)]
+    filter: PostFilter,
+    /// Order to list the posts in, instead of the server's; fetches every matching post first
+    #[arg(long, value_enum)]
+    sort: Option<PostSort>,
+    #[command(flatten)]
+    list: ListArgs,
+}
+
+/// Which posts to list. Filtering happens locally, after fetching each page.
+#[derive(Args, Default)]
+struct PostFilter {
+    /// Only list posts with this tag; repeat to require several
+    #[arg(short, long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Only list published posts
+    #[arg(long, conflicts_with = "drafts")]
+    published: bool,
+    /// Only list drafts
+    #[arg(long)]
+    drafts: bool,
+    /// Only list posts published (or created, for drafts) on or after this date, e.g. 2026-01-31
+    #[arg(long, value_name = "DATE")]
+    since: Option<chrono::NaiveDate>,
+    /// Only list posts published (or created, for drafts) on or before this date
+    #[arg(long, value_name = "DATE")]
+    until: Option<chrono::NaiveDate>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PostSort {
+    /// Most recently published or created first
+    Newest,
+    /// Least recently published or created first
+    Oldest,
+    /// Most recently updated first
+    Updated,
+    /// Alphabetically by title
+    Title,
+}
+
+#[derive(Subcommand)]
+enum PostCommands {
+    /// <ID|SLUG> [--space <SLUG>] [--body] - Show the details of a post
+    Show {
+        /// Post ID, or slug
+        post: String,
+        /// Space to look the slug up in (posts outside any space are searched otherwise)
+        #[arg(short, long)]
+        space: Option<String>,
+        /// Also print the markdown body
+        #[arg(short, long)]
+        body: bool,
+    },
+    /// <ID> --to-space <SLUG> | --to-profile - Move a post to another space or to your profile
+    Move {
+        /// Post ID
+        id: u64,
+        #[command(flatten)]
+        to: PostDestination,
+    },
+    /// <ID> --to-space <SLUG> | --to-profile - Copy a post to a space or to your profile
+    Copy {
+        /// Post ID
+        id: u64,
+        #[command(flatten)]
+        to: PostDestination,
+    },
+}
+
+/// Where `post move` and `post copy` put a post
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct PostDestination {
+    /// Put the post in this space
+    #[arg(long, value_name = "SLUG")]
+    to_space: Option<String>,
+    /// Put the post on your profile, outside any space
+    #[arg(long)]
+    to_profile: bool,
+}
+
+/// How markdown files are turned into posts
+#[derive(Args, Clone, Copy)]
+struct ContentArgs {
+    /// Send the whole file, frontmatter included, as the post content
+    #[arg(long)]
+    raw: bool,
+    /// Warn about malformed or unknown frontmatter instead of failing
+    #[arg(long)]
+    lenient: bool,
+    /// Leave links to local images and files as they are instead of uploading them
+    #[arg(long)]
+    no_upload: bool,
+}
+
 #[derive(Subcommand)]
 enum SpaceCommands {
     /// --name <NAME> [--slug <SLUG>] [--description <DESC>] [--visibility public|unlisted|private] - Create a new space
@@ -77,454 +356,2571 @@ enum SpaceCommands {
         /// Space slug
         slug: String,
     },
+    /// <SLUG> [--name <NAME>] [--description <DESC>] [--visibility public|unlisted|private] - Change a space
+    Update {
+        /// Space slug
+        slug: String,
+        /// New display name
+        #[arg(short, long)]
+        name: Option<String>,
+        /// New description; pass "" to clear it
+        #[arg(short, long)]
+        description: Option<String>,
+        /// New visibility: public, unlisted, or private
+        #[arg(short, long)]
+        visibility: Option<String>,
+    },
+    /// <SLUG> <NEW_SLUG> - Change the slug, and with it the URL, of a space
+    Rename {
+        /// Current space slug
+        slug: String,
+        /// New space slug
+        new_slug: String,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// <SLUG> - Delete a space
+    Delete {
+        /// Space slug
+        slug: String,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// <SLUG> - List the members and pending invitations of a space
+    Members {
+        /// Space slug
+        slug: String,
+        #[command(flatten)]
+        list: ListArgs,
+    },
+    /// <SLUG> <USER|EMAIL> [--role reader|writer|admin] - Add someone to a space
+    Invite {
+        /// Space slug
+        slug: String,
+        /// Username of an existing account, or an email address to send an invitation to
+        member: String,
+        /// What the member may do: read posts, also publish, or also manage the space
+        #[arg(short, long, default_value = "reader", value_parser = ["reader", "writer", "admin"])]
+        role: String,
+    },
+    /// <SLUG> <USER|EMAIL> - Remove a member or withdraw an invitation
+    RemoveMember {
+        /// Space slug
+        slug: String,
+        /// Username or email address
+        member: String,
+    },
+    /// <COMMAND> - Replace or remove the access token behind a space's share URL
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
-struct Config {
-    api_token: String,
-    api_url: String,
+#[derive(Subcommand)]
+enum TokenCommands {
+    /// <SLUG> [--expires <DURATION>] - Issue a new share URL; the old one stops working
+    Rotate {
+        /// Space slug
+        slug: String,
+        /// Let the new share URL stop working after this long, e.g. 30m, 12h, 7d or 2w
+        #[arg(short, long)]
+        expires: Option<String>,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// <SLUG> - Remove the access token, so the share URL stops working
+    Revoke {
+        /// Space slug
+        slug: String,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
-#[derive(Deserialize)]
-struct PostsResponse {
-    posts: Vec<Post>,
-}
+const DEFAULT_PROFILE: &str = "default";
 
-#[derive(Deserialize, Debug)]
-struct PostResponse {
-    post: Post,
-}
+const DEFAULT_API_URL: &str = "https://moyn.dev";
 
-#[derive(Deserialize, Debug)]
-struct Post {
-    id: u64,
-    title: String,
-    slug: String,
-    url: String,
-}
+/// Service name for tokens in the system keyring
+const KEYRING_SERVICE: &str = "moyn";
 
-#[derive(Serialize)]
-struct CreatePostRequest {
-    post: CreatePost,
+#[derive(Serialize, Deserialize, Default)]
+struct ConfigFile {
+    /// Profile used when none is given with --profile or MOYN_PROFILE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Config>,
 }
 
-#[derive(Serialize)]
-struct CreatePost {
-    title: String,
-    content: String,
-    published: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    slug: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    tags: Option<Vec<String>>,
+impl ConfigFile {
+    fn current_profile(&self) -> &str {
+        self.current.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
 }
 
+/// Config files from before profiles held a single account
 #[derive(Deserialize)]
-struct SpacesResponse {
-    spaces: Vec<Space>,
+#[serde(untagged)]
+enum StoredConfig {
+    Legacy(Config),
+    Profiles(ConfigFile),
 }
 
-#[derive(Deserialize, Debug)]
-struct SpaceResponse {
-    space: Space,
+/// Who a profile's token belongs to
+#[derive(Serialize)]
+struct Identity {
+    user: User,
+    instance: String,
+    profile: String,
+    scopes: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct Space {
-    slug: String,
-    name: String,
-    description: Option<String>,
-    visibility: String,
-    access_token: Option<String>,
-    url: String,
-    token_url: Option<String>,
+/// Types that can be printed as tab-separated rows
+trait Tsv {
+    const HEADER: &'static [&'static str];
+
+    fn tsv_row(&self) -> Vec<String>;
 }
 
-#[derive(Serialize)]
-struct CreateSpaceRequest {
-    space: CreateSpace,
+impl Tsv for Post {
+    const HEADER: &'static [&'static str] = &[
+        "id", "title", "slug", "url", "space", "published", "tags", "published_at", "created_at", "updated_at",
+    ];
+
+    fn tsv_row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.title.clone(),
+            self.slug.clone(),
+            self.url.clone(),
+            self.space.clone().unwrap_or_default(),
+            self.published.map(|p| p.to_string()).unwrap_or_default(),
+            self.tags.join(","),
+            self.published_at.clone().unwrap_or_default(),
+            self.created_at.clone().unwrap_or_default(),
+            self.updated_at.clone().unwrap_or_default(),
+        ]
+    }
 }
 
-#[derive(Serialize)]
-struct CreateSpace {
-    name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    slug: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    visibility: Option<String>,
+impl Tsv for Identity {
+    const HEADER: &'static [&'static str] = &["username", "name", "instance", "profile", "scopes"];
+
+    fn tsv_row(&self) -> Vec<String> {
+        vec![
+            self.user.username.clone(),
+            self.user.name.clone().unwrap_or_default(),
+            self.instance.clone(),
+            self.profile.clone(),
+            self.scopes.join(","),
+        ]
+    }
 }
 
-#[derive(Deserialize, Default)]
-struct Frontmatter {
-    title: Option<String>,
-    published: Option<bool>,
-    tags: Option<Vec<String>>,
-    slug: Option<String>,
-    space: Option<String>,
+impl Tsv for Space {
+    const HEADER: &'static [&'static str] =
+        &["slug", "name", "description", "visibility", "url", "token_url", "access_token", "token_expires_at"];
+
+    fn tsv_row(&self) -> Vec<String> {
+        vec![
+            self.slug.clone(),
+            self.name.clone(),
+            self.description.clone().unwrap_or_default(),
+            self.visibility.clone(),
+            self.url.clone(),
+            self.token_url.clone().unwrap_or_default(),
+            self.access_token.clone().unwrap_or_default(),
+            self.token_expires_at.clone().unwrap_or_default(),
+        ]
+    }
 }
 
-struct ParsedContent {
-    frontmatter: Frontmatter,
-    content: String,
+/// Types that can be listed as a table for people to read
+trait Table {
+    /// Printed instead of the table when there is nothing to list
+    const EMPTY: &'static str;
+
+    fn print_header();
+
+    fn print_row(&self);
 }
 
-fn parse_frontmatter(content: &str) -> ParsedContent {
-    let trimmed = content.trim_start();
+impl Table for Post {
+    const EMPTY: &'static str = "No posts yet.";
 
-    if !trimmed.starts_with("---") {
-        return ParsedContent {
-            frontmatter: Frontmatter::default(),
-            content: content.to_string(),
-        };
+    fn print_header() {
+        println!("{:<6} {:<40} URL", "ID", "TITLE");
+        println!("{}", "-".repeat(80));
     }
 
-    // Find the end of frontmatter
-    let after_first_marker = &trimmed[3..];
-    if let Some(end_pos) = after_first_marker.find("\n---") {
-        let yaml_content = &after_first_marker[..end_pos].trim();
-        let remaining_content = &after_first_marker[end_pos + 4..];
-
-        // Parse the YAML
-        match serde_yaml::from_str::<Frontmatter>(yaml_content) {
-            Ok(fm) => ParsedContent {
-                frontmatter: fm,
-                content: remaining_content.trim_start_matches('\n').to_string(),
-            },
-            Err(_) => ParsedContent {
-                frontmatter: Frontmatter::default(),
-                content: content.to_string(),
-            },
-        }
-    } else {
-        ParsedContent {
-            frontmatter: Frontmatter::default(),
-            content: content.to_string(),
-        }
+    fn print_row(&self) {
+        println!("{:<6} {:<40} {}", self.id, truncate(&self.title, 38), self.url);
     }
 }
 
-fn config_path() -> PathBuf {
-    dirs::config_dir()
-        .expect("Could not find config directory")
-        .join("moyn")
-        .join("config.json")
-}
+impl Table for Space {
+    const EMPTY: &'static str = "No spaces yet. Create one with `moyn space create <slug>`";
 
-fn load_config() -> Result<Config, String> {
-    let path = config_path();
-    let content = fs::read_to_string(&path)
-        .map_err(|_| "Not logged in. Run `moyn login` first.".to_string())?;
-    serde_json::from_str(&content).map_err(|e| format!("Invalid config: {}", e))
-}
+    fn print_header() {
+        println!("{:<20} {:<30} {:<10} URL", "SLUG", "NAME", "VISIBILITY");
+        println!("{}", "-".repeat(75));
+    }
 
-fn save_config(config: &Config) -> Result<(), String> {
-    let path = config_path();
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Could not create config dir: {}", e))?;
+    fn print_row(&self) {
+        println!(
+            "{:<20} {:<30} {:<10} {}",
+            truncate(&self.slug, 18),
+            truncate(&self.name, 28),
+            self.visibility,
+            self.url
+        );
     }
-    let content = serde_json::to_string_pretty(config).map_err(|e| format!("Could not serialize config: {}", e))?;
-    fs::write(&path, content).map_err(|e| format!("Could not write config: {}", e))
 }
 
-fn client(config: &Config) -> reqwest::blocking::Client {
-    reqwest::blocking::Client::new()
+impl Tsv for Member {
+    const HEADER: &'static [&'static str] = &["username", "name", "email", "role", "status"];
+
+    fn tsv_row(&self) -> Vec<String> {
+        vec![
+            self.username.clone().unwrap_or_default(),
+            self.name.clone().unwrap_or_default(),
+            self.email.clone().unwrap_or_default(),
+            self.role.clone(),
+            self.status.clone().unwrap_or_default(),
+        ]
+    }
 }
 
-fn login() -> Result<(), String> {
-    print!("Enter your API token (from your profile page): ");
-    io::stdout().flush().unwrap();
-
-    let mut token = String::new();
-    io::stdin()
-        .read_line(&mut token)
-        .map_err(|e| format!("Could not read input: {}", e))?;
-    let token = token.trim().to_string();
+impl Table for Member {
+    const EMPTY: &'static str = "No members yet. Add one with `moyn space invite <slug> <user>`";
 
-    if !token.starts_with("moyn_") {
-        return Err("Invalid token format. Token should start with 'moyn_'".to_string());
+    fn print_header() {
+        println!("{:<30} {:<25} {:<8} STATUS", "MEMBER", "NAME", "ROLE");
+        println!("{}", "-".repeat(75));
     }
 
-    print!("Enter API URL [https://moyn.dev]: ");
-    io::stdout().flush().unwrap();
+    fn print_row(&self) {
+        println!(
+            "{:<30} {:<25} {:<8} {}",
+            truncate(&member_label(self), 28),
+            truncate(self.name.as_deref().unwrap_or_default(), 23),
+            self.role,
+            self.status.as_deref().unwrap_or("active")
+        );
+    }
+}
 
-    let mut url = String::new();
-    io::stdin()
-        .read_line(&mut url)
-        .map_err(|e| format!("Could not read input: {}", e))?;
-    let url = url.trim();
-    let url = if url.is_empty() {
-        "https://moyn.dev".to_string()
-    } else {
-        url.to_string()
-    };
+/// `@username`, or the email address of a pending invitation
+fn member_label(member: &Member) -> String {
+    match (&member.username, &member.email) {
+        (Some(username), _) => format!("@{}", username),
+        (None, Some(email)) => email.clone(),
+        (None, None) => "(unknown)".to_string(),
+    }
+}
 
-    let config = Config {
-        api_token: token,
-        api_url: url,
+/// Print up to `limit` items of a listing in any format. Tables and TSV are
+/// printed while later pages are still being fetched; JSON and YAML once
+/// everything has arrived. If there was more, a note goes to stderr.
+fn print_list<T: Serialize + Tsv + Table>(
+    format: OutputFormat,
+    mut items: impl Iterator<Item = Result<T, Error>>,
+    limit: Option<usize>,
+) -> Result<(), Error> {
+    let limit = limit.unwrap_or(usize::MAX);
+
+    let count = match format {
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let items = items.by_ref().take(limit).collect::<Result<Vec<_>, _>>()?;
+            print_items(format, &items)?;
+            items.len()
+        }
+        OutputFormat::Tsv => {
+            println!("{}", T::HEADER.join("\t"));
+            let mut count = 0;
+            for item in items.by_ref().take(limit) {
+                let row: Vec<String> = item?.tsv_row().iter().map(|cell| tsv_escape(cell)).collect();
+                println!("{}", row.join("\t"));
+                count += 1;
+            }
+            count
+        }
+        OutputFormat::Table => {
+            let mut count = 0;
+            for item in items.by_ref().take(limit) {
+                let item = item?;
+                if count == 0 {
+                    T::print_header();
+                }
+                item.print_row();
+                count += 1;
+            }
+            if count == 0 {
+                println!("{}", T::EMPTY);
+            }
+            count
+        }
     };
 
-    save_config(&config)?;
-    println!("Logged in successfully!");
+    if count > 0 && count == limit && matches!(items.next(), Some(Ok(_))) {
+        eprintln!("\nShowing the first {}. Use --limit or --all to see more.", count);
+    }
     Ok(())
 }
 
-fn extract_title(content: &str, filename: &str) -> String {
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("# ") {
-            return trimmed[2..].trim().to_string();
+/// Print items as JSON, YAML or TSV. Table output is up to each command.
+fn print_items<T: Serialize + Tsv>(format: OutputFormat, items: &[T]) -> Result<(), Error> {
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(items).map_err(|e| format!("Could not serialize output: {}", e))?
+        ),
+        OutputFormat::Yaml => print!(
+            "{}",
+            serde_yaml::to_string(items).map_err(|e| format!("Could not serialize output: {}", e))?
+        ),
+        // Commands print their own tables, so this only sees Tsv
+        OutputFormat::Tsv | OutputFormat::Table => {
+            println!("{}", T::HEADER.join("\t"));
+            for item in items {
+                let row: Vec<String> = item.tsv_row().iter().map(|cell| tsv_escape(cell)).collect();
+                println!("{}", row.join("\t"));
+            }
         }
     }
-    // Fallback to filename without extension
-    PathBuf::from(filename)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("Untitled")
-        .to_string()
+    Ok(())
+}
+
+/// Print a single item as a JSON or YAML object, or a one-row TSV table.
+fn print_item<T: Serialize + Tsv>(format: OutputFormat, item: &T) -> Result<(), Error> {
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(item).map_err(|e| format!("Could not serialize output: {}", e))?
+        ),
+        OutputFormat::Yaml => print!(
+            "{}",
+            serde_yaml::to_string(item).map_err(|e| format!("Could not serialize output: {}", e))?
+        ),
+        OutputFormat::Tsv | OutputFormat::Table => print_items(format, std::slice::from_ref(item))?,
+    }
+    Ok(())
 }
 
-fn publish(file: PathBuf) -> Result<(), String> {
-    let config = load_config()?;
+fn tsv_escape(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
 
-    let raw_content = fs::read_to_string(&file)
-        .map_err(|e| format!("Could not read file: {}", e))?;
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct Frontmatter {
+    id: Option<u64>,
+    title: Option<String>,
+    published: Option<bool>,
+    tags: Option<Vec<String>>,
+    slug: Option<String>,
+    space: Option<String>,
+    /// Written by `--write-back` for reference; never sent to the server
+    url: Option<String>,
+    published_at: Option<String>,
+}
 
-    let parsed = parse_frontmatter(&raw_content);
+const FRONTMATTER_KEYS: &[&str] = &["id", "title", "published", "tags", "slug", "space", "url", "published_at"];
 
-    // Use frontmatter title, or fall back to heading/filename extraction
-    let title = parsed.frontmatter.title
-        .unwrap_or_else(|| extract_title(&parsed.content, file.to_str().unwrap_or("post")));
+#[derive(Debug)]
+struct ParsedContent {
+    frontmatter: Frontmatter,
+    content: String,
+    /// Problems skipped over in lenient mode
+    warnings: Vec<FrontmatterError>,
+}
 
-    // Use frontmatter published value, or default to true
-    let published = parsed.frontmatter.published.unwrap_or(true);
+/// A problem in a document's frontmatter. Lines and columns are 1-based and
+/// count from the start of the document.
+#[derive(Debug)]
+struct FrontmatterError {
+    line: usize,
+    column: usize,
+    message: String,
+}
 
-    let request = CreatePostRequest {
-        post: CreatePost {
-            title: title.clone(),
-            content: raw_content,
-            published,
-            slug: parsed.frontmatter.slug,
-            tags: parsed.frontmatter.tags,
-        },
-    };
+impl FrontmatterError {
+    fn at(&self, file: &Path) -> String {
+        format!("{}:{}:{}: {}", file.display(), self.line, self.column, self.message)
+    }
+}
 
-    // Determine endpoint based on space
-    let endpoint = match &parsed.frontmatter.space {
-        Some(space) => format!("{}/api/v1/spaces/{}/posts", config.api_url, space),
-        None => format!("{}/api/v1/posts", config.api_url),
+/// Split a document into frontmatter and body. Unknown keys and invalid YAML
+/// are errors unless `lenient` is set, in which case they are reported as
+/// warnings and skipped.
+fn parse_frontmatter(content: &str, lenient: bool) -> Result<ParsedContent, FrontmatterError> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    let Some(block) = find_frontmatter(content) else {
+        let mut warnings = Vec::new();
+        if content.trim_start().lines().next().map(str::trim_end) == Some("---") {
+            let error = FrontmatterError {
+                line: content[..content.len() - content.trim_start().len()].matches('\n').count() + 1,
+                column: 1,
+                message: "frontmatter is not closed, expected a line with just `---`".to_string(),
+            };
+            if !lenient {
+                return Err(error);
+            }
+            warnings.push(error);
+        }
+        return Ok(ParsedContent {
+            frontmatter: Frontmatter::default(),
+            content: content.to_string(),
+            warnings,
+        });
     };
 
-    let response = client(&config)
-        .post(&endpoint)
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .json(&request)
-        .send()
-        .map_err(|e| format!("Request failed: {}", e))?;
+    let yaml_content = &content[block.yaml.clone()];
+    let line_offset = content[..block.yaml.start].matches('\n').count();
+    let remaining_content = content[block.body..].trim_start_matches(['\r', '\n']).to_string();
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().unwrap_or_default();
-        return Err(format!("Failed to publish: {} - {}", status, body));
+    if yaml_content.trim().is_empty() {
+        return Ok(ParsedContent {
+            frontmatter: Frontmatter::default(),
+            content: remaining_content,
+            warnings: Vec::new(),
+        });
     }
 
-    let post_response: PostResponse = response
-        .json()
-        .map_err(|e| format!("Could not parse response: {}", e))?;
-
-    println!("Published: {}", post_response.post.title);
-    println!("URL: {}", post_response.post.url);
-    Ok(())
+    // Parse the YAML
+    match serde_yaml::from_str::<Frontmatter>(yaml_content) {
+        Ok(fm) => Ok(ParsedContent {
+            frontmatter: fm,
+            content: remaining_content,
+            warnings: Vec::new(),
+        }),
+        Err(e) if !lenient => Err(frontmatter_error(&e, line_offset)),
+        Err(e) => {
+            let (frontmatter, warnings) = parse_frontmatter_lenient(yaml_content, line_offset, &e);
+            Ok(ParsedContent {
+                frontmatter,
+                content: remaining_content,
+                warnings,
+            })
+        }
+    }
 }
 
-fn posts() -> Result<(), String> {
-    let config = load_config()?;
+/// Parse frontmatter that failed strict parsing: drop unknown keys, and fall
+/// back to empty frontmatter if it still doesn't parse.
+fn parse_frontmatter_lenient(
+    yaml_content: &str,
+    line_offset: usize,
+    error: &serde_yaml::Error,
+) -> (Frontmatter, Vec<FrontmatterError>) {
+    let mut warnings = Vec::new();
+
+    let Ok(mut value) = serde_yaml::from_str::<serde_yaml::Value>(yaml_content) else {
+        warnings.push(frontmatter_error(error, line_offset));
+        return (Frontmatter::default(), warnings);
+    };
 
-    let response = client(&config)
-        .get(format!("{}/api/v1/posts", config.api_url))
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .send()
-        .map_err(|e| format!("Request failed: {}", e))?;
+    if let Some(mapping) = value.as_mapping_mut() {
+        mapping.retain(|key, _| {
+            let key = key.as_str().unwrap_or_default();
+            if FRONTMATTER_KEYS.contains(&key) {
+                return true;
+            }
+            let line = yaml_content
+                .lines()
+                .position(|line| line.strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with(':')))
+                .unwrap_or(0);
+            warnings.push(FrontmatterError {
+                line: line_offset + line + 1,
+                column: 1,
+                message: unknown_key_message(key),
+            });
+            false
+        });
+    }
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().unwrap_or_default();
-        return Err(format!("Failed to fetch posts: {} - {}", status, body));
+    match serde_yaml::from_value(value) {
+        Ok(frontmatter) => (frontmatter, warnings),
+        Err(e) => {
+            warnings.push(FrontmatterError {
+                line: line_offset + 1,
+                column: 1,
+                message: format!("{}, ignoring frontmatter", e),
+            });
+            (Frontmatter::default(), warnings)
+        }
     }
+}
 
-    let posts_response: PostsResponse = response
-        .json()
-        .map_err(|e| format!("Could not parse response: {}", e))?;
+fn frontmatter_error(error: &serde_yaml::Error, line_offset: usize) -> FrontmatterError {
+    let message = error.to_string();
+    // The position is reported separately
+    let message = message.split(" at line ").next().unwrap_or(&message);
+
+    let message = match message
+        .strip_prefix("unknown field `")
+        .and_then(|rest| rest.split_once('`'))
+    {
+        Some((key, _)) => unknown_key_message(key),
+        None => message.to_string(),
+    };
 
-    if posts_response.posts.is_empty() {
-        println!("No posts yet.");
-        return Ok(());
+    let (line, column) = error
+        .location()
+        .map_or((1, 1), |location| (location.line(), location.column()));
+
+    FrontmatterError {
+        line: line_offset + line,
+        column,
+        message,
     }
+}
 
-    println!("{:<6} {:<40} {}", "ID", "TITLE", "URL");
-    println!("{}", "-".repeat(80));
-    for post in posts_response.posts {
-        println!("{:<6} {:<40} {}", post.id, truncate(&post.title, 38), post.url);
+fn unknown_key_message(key: &str) -> String {
+    // Allow fewer typos in short keys so `ab` doesn't suggest `id`
+    let max_distance = if key.len() <= 3 { 1 } else { 2 };
+    let suggestion = FRONTMATTER_KEYS
+        .iter()
+        .map(|known| (edit_distance(key, known), known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min();
+
+    match suggestion {
+        Some((_, known)) => format!("unknown frontmatter key `{}` (did you mean `{}`?)", key, known),
+        None => format!(
+            "unknown frontmatter key `{}`, expected one of: {}",
+            key,
+            FRONTMATTER_KEYS.join(", ")
+        ),
     }
-    Ok(())
 }
 
-fn truncate(s: &str, max: usize) -> String {
-    if s.len() <= max {
-        s.to_string()
-    } else {
-        format!("{}...", &s[..max - 3])
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
     }
+    row[b.len()]
 }
 
-fn delete(id: u64) -> Result<(), String> {
-    let config = load_config()?;
+/// Byte ranges of a frontmatter block within a document.
+struct FrontmatterBlock {
+    /// The YAML between the `---` markers
+    yaml: Range<usize>,
+    /// Start of the content after the closing marker
+    body: usize,
+}
 
-    let response = client(&config)
-        .delete(format!("{}/api/v1/posts/{}", config.api_url, id))
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .send()
-        .map_err(|e| format!("Request failed: {}", e))?;
+/// Find the frontmatter block: a `---` line at the start of the document
+/// (after any BOM or blank lines) up to the next line that is exactly `---`.
+fn find_frontmatter(content: &str) -> Option<FrontmatterBlock> {
+    let start = content.len()
+        - content
+            .trim_start_matches(|c: char| c == '\u{feff}' || c.is_whitespace())
+            .len();
+    let mut lines = content[start..].split_inclusive('\n');
+
+    let first = lines.next()?;
+    if first.trim_end() != "---" {
+        return None;
+    }
 
-    if response.status().as_u16() == 204 {
-        println!("Post {} deleted.", id);
-        Ok(())
-    } else if response.status().as_u16() == 404 {
-        Err(format!("Post {} not found.", id))
-    } else {
-        let status = response.status();
-        let body = response.text().unwrap_or_default();
-        Err(format!("Failed to delete: {} - {}", status, body))
+    let yaml_start = start + first.len();
+    let mut pos = yaml_start;
+    for line in lines {
+        if line.trim_end() == "---" {
+            return Some(FrontmatterBlock {
+                yaml: yaml_start..pos,
+                body: pos + line.len(),
+            });
+        }
+        pos += line.len();
     }
+    None
 }
 
-fn spaces() -> Result<(), String> {
-    let config = load_config()?;
+/// Render a string as a YAML scalar, quoting it only when needed.
+fn yaml_scalar(value: &str) -> String {
+    serde_yaml::to_string(value)
+        .map(|s| s.trim_end().to_string())
+        .unwrap_or_else(|_| format!("{:?}", value))
+}
 
-    let response = client(&config)
-        .get(format!("{}/api/v1/spaces", config.api_url))
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .send()
-        .map_err(|e| format!("Request failed: {}", e))?;
+/// Render strings as a YAML flow sequence, e.g. `[rust, cli]`
+fn yaml_list(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|value| yaml_scalar(value)).collect();
+    format!("[{}]", values.join(", "))
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().unwrap_or_default();
-        return Err(format!("Failed to fetch spaces: {} - {}", status, body));
-    }
+/// Set top-level keys in a document's frontmatter, leaving every other line
+/// as it was. Existing keys are replaced in place, missing ones are appended,
+/// and a frontmatter block is added if the document has none. Values must
+/// already be rendered as YAML.
+fn set_frontmatter_fields(content: &str, fields: &[(&str, String)]) -> String {
+    let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let Some(block) = find_frontmatter(content) else {
+        let mut out = format!("---{}", newline);
+        for (key, value) in fields {
+            out.push_str(&format!("{}: {}{}", key, value, newline));
+        }
+        out.push_str(&format!("---{}{}", newline, newline));
+        out.push_str(content);
+        return out;
+    };
 
-    let spaces_response: SpacesResponse = response
-        .json()
-        .map_err(|e| format!("Could not parse response: {}", e))?;
+    let mut out = content[..block.yaml.start].to_string();
+    let mut pending: Vec<&(&str, String)> = fields.iter().collect();
+    let mut replacing = false;
 
-    if spaces_response.spaces.is_empty() {
-        println!("No spaces yet. Create one with `moyn space create <slug>`");
+    for line in content[block.yaml.clone()].split_inclusive('\n') {
+        // Drop the indented or list continuation lines of a replaced key
+        if replacing && (line.starts_with([' ', '\t']) || line.starts_with("- ")) {
+            continue;
+        }
+        replacing = false;
+
+        match pending.iter().position(|(field, _)| Some(*field) == frontmatter_key(line)) {
+            Some(index) => {
+                let (field, value) = pending.remove(index);
+                out.push_str(&format!("{}: {}{}", field, value, newline));
+                replacing = true;
+            }
+            None => out.push_str(line),
+        }
+    }
+
+    if !out.ends_with('\n') {
+        out.push_str(newline);
+    }
+    for (field, value) in pending {
+        out.push_str(&format!("{}: {}{}", field, value, newline));
+    }
+
+    out.push_str(&content[block.yaml.end..]);
+    out
+}
+
+/// Remove top-level keys, with their indented or list continuation lines,
+/// from a document's frontmatter, leaving every other line as it was.
+fn remove_frontmatter_fields(content: &str, keys: &[&str]) -> String {
+    let Some(block) = find_frontmatter(content) else {
+        return content.to_string();
+    };
+
+    let mut out = content[..block.yaml.start].to_string();
+    let mut removing = false;
+
+    for line in content[block.yaml.clone()].split_inclusive('\n') {
+        if removing && (line.starts_with([' ', '\t']) || line.starts_with("- ")) {
+            continue;
+        }
+        removing = frontmatter_key(line).is_some_and(|key| keys.contains(&key));
+        if !removing {
+            out.push_str(line);
+        }
+    }
+
+    out.push_str(&content[block.yaml.end..]);
+    out
+}
+
+/// The key of a top-level `key: value` line in a frontmatter block
+fn frontmatter_key(line: &str) -> Option<&str> {
+    line.split_once(':')
+        .filter(|_| !line.starts_with([' ', '\t', '#']))
+        .map(|(key, _)| key.trim())
+}
+
+fn config_path() -> PathBuf {
+    dirs::config_dir()
+        .expect("Could not find config directory")
+        .join("moyn")
+        .join("config.json")
+}
+
+/// Local record of which files have been published, so re-publishing a file
+/// updates its post instead of creating a duplicate.
+#[derive(Serialize, Deserialize, Default)]
+struct State {
+    /// The instance the posts and media were published to. Files from before
+    /// this was recorded are claimed by the first instance that uses them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_url: Option<String>,
+    /// Canonical file path -> published post
+    #[serde(default)]
+    posts: BTreeMap<String, PublishedFile>,
+    /// SHA-256 of an uploaded file -> its URL
+    #[serde(default)]
+    media: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PublishedFile {
+    id: u64,
+    /// Hash of the request last sent for this file
+    #[serde(default)]
+    hash: Option<String>,
+}
+
+fn templates_dir() -> PathBuf {
+    config_path().with_file_name("templates")
+}
+
+/// Each profile keeps its own record of published files, since post IDs
+/// belong to one account on one instance. `load_state` refuses a record made
+/// for another instance, e.g. when `MOYN_API_URL` points the profile elsewhere.
+fn state_path(config: &Config) -> PathBuf {
+    if config.profile == DEFAULT_PROFILE {
+        config_path().with_file_name("state.json")
+    } else {
+        config_path().with_file_name(format!("state-{}.json", config.profile))
+    }
+}
+
+fn state_key(file: &Path) -> String {
+    fs::canonicalize(file)
+        .unwrap_or_else(|_| file.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn load_state(config: &Config) -> Result<State, Error> {
+    let path = state_path(config);
+    let state = match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| Error::Other(format!("Invalid state file: {}", e)))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => State::default(),
+        Err(e) => return Err(Error::Other(format!("Could not read state file: {}", e))),
+    };
+    claim_state(state, &config.api_url)
+        .map_err(|other| Error::Config(format!(
+            "{} records the posts published to {}, not {}. Use a separate profile for each instance, e.g. with MOYN_PROFILE=staging.",
+            path.display(), other, config.api_url
+        )))
+}
+
+/// Take over a state for `api_url`, or return the instance it belongs to instead.
+fn claim_state(mut state: State, api_url: &str) -> Result<State, String> {
+    let api_url = api_url.trim_end_matches('/');
+    match state.api_url.take() {
+        Some(other) if other.trim_end_matches('/') != api_url => Err(other),
+        _ => {
+            state.api_url = Some(api_url.to_string());
+            Ok(state)
+        }
+    }
+}
+
+fn save_state(config: &Config, state: &State) -> Result<(), Error> {
+    let path = state_path(config);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Could not create config dir: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(state).map_err(|e| format!("Could not serialize state: {}", e))?;
+    fs::write(&path, content).map_err(|e| Error::Other(format!("Could not write state file: {}", e)))
+}
+
+fn load_config_file() -> Result<ConfigFile, Error> {
+    let path = config_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ConfigFile::default()),
+        Err(e) => return Err(Error::Config(format!("Could not read config: {}", e))),
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(&path).is_ok_and(|metadata| metadata.permissions().mode() & 0o004 != 0) {
+            eprintln!(
+                "Warning: {} is readable by other users. Run `chmod 600 {}` to protect it.",
+                path.display(),
+                path.display()
+            );
+        }
+    }
+
+    match serde_json::from_str(&content).map_err(|e| Error::Config(format!("Invalid config: {}", e)))? {
+        StoredConfig::Profiles(config_file) => Ok(config_file),
+        StoredConfig::Legacy(mut config) => {
+            // Move the single account into the default profile, and its
+            // token out of the file if there's a keyring to put it in
+            if store_token(DEFAULT_PROFILE, &config.api_token).is_ok() {
+                config.api_token.clear();
+            }
+            let config_file = ConfigFile {
+                current: Some(DEFAULT_PROFILE.to_string()),
+                profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), config)]),
+            };
+            save_config_file(&config_file)?;
+            Ok(config_file)
+        }
+    }
+}
+
+/// Write the config file readable by its owner only.
+fn save_config_file(config_file: &ConfigFile) -> Result<(), Error> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Could not create config dir: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(config_file).map_err(|e| format!("Could not serialize config: {}", e))?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path).map_err(|e| format!("Could not write config: {}", e))?;
+
+    // The mode above only applies to new files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Could not set config permissions: {}", e))?;
+    }
+
+    file.write_all(content.as_bytes())
+        .map_err(|e| Error::Config(format!("Could not write config: {}", e)))
+}
+
+fn keyring_entry(profile: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, profile)
+}
+
+fn store_token(profile: &str, token: &str) -> keyring::Result<()> {
+    keyring_entry(profile)?.set_password(token)
+}
+
+fn load_token(profile: &str) -> Result<String, Error> {
+    keyring_entry(profile)
+        .and_then(|entry| entry.get_password())
+        .map_err(|e| {
+            Error::Config(format!(
+                "Could not read the API token for profile '{}' from the system keyring: {}. Run `moyn login` again.",
+                profile, e
+            ))
+        })
+}
+
+fn delete_token(profile: &str) {
+    // Nothing to do if the token was never in the keyring
+    let _ = keyring_entry(profile).and_then(|entry| entry.delete_credential());
+}
+
+/// Load the given profile, or the current one.
+/// `MOYN_API_TOKEN` and `MOYN_API_URL` override the profile's settings, and
+/// with a token set no config file is needed at all.
+fn load_config(profile: Option<&str>) -> Result<Config, Error> {
+    let env_token = std::env::var("MOYN_API_TOKEN").ok().filter(|token| !token.is_empty());
+    let env_url = std::env::var("MOYN_API_URL").ok().filter(|url| !url.is_empty());
+
+    let config_file = load_config_file()?;
+    let name = profile.unwrap_or(config_file.current_profile());
+
+    let mut config = match config_file.profiles.get(name) {
+        Some(config) => config.clone(),
+        None if env_token.is_some() => Config {
+            api_url: DEFAULT_API_URL.to_string(),
+            ..Config::default()
+        },
+        None if name == DEFAULT_PROFILE => {
+            return Err(Error::Config(
+                "Not logged in. Run `moyn login` first, or set MOYN_API_TOKEN.".to_string(),
+            ));
+        }
+        None => {
+            return Err(Error::Config(format!(
+                "Profile '{}' not found. Run `moyn login --profile {}` to create it.",
+                name, name
+            )));
+        }
+    };
+    config.profile = name.to_string();
+
+    if let Some(url) = env_url {
+        config.api_url = url.trim_end_matches('/').to_string();
+    }
+    if let Some(token) = env_token {
+        config.api_token = token;
+    } else if config.api_token.is_empty() {
+        config.api_token = load_token(name)?;
+    }
+    Ok(config)
+}
+
+/// Check the token and API URL by asking who the token belongs to.
+fn fetch_me(client: &MoynClient) -> Result<Me, Error> {
+    let url = &client.config().api_url;
+    client.me().map_err(|e| match e {
+        Error::Auth(_) => Error::Auth(format!("The API token was rejected by {}.", url)),
+        e => e,
+    })
+}
+
+/// Load a profile's config and set up a client for it.
+fn connect(profile: Option<&str>) -> Result<MoynClient, Error> {
+    new_client(load_config(profile)?)
+}
+
+/// Set up a client that says on stderr when it retries a request.
+fn new_client(config: Config) -> Result<MoynClient, Error> {
+    Ok(MoynClient::new(config)?
+        .on_retry(|retry| eprintln!("{}, retrying in {:.1}s...", retry.reason, retry.delay.as_secs_f64())))
+}
+
+/// Load a profile's config for commands that don't contact the server. When
+/// not logged in, the profile's API URL is used without a token, or the
+/// default instance if there is no such profile.
+fn load_offline_config(profile: Option<&str>) -> Result<Config, Error> {
+    match load_config(profile) {
+        Err(Error::Config(_)) => {
+            let config_file = load_config_file().unwrap_or_default();
+            let name = profile.unwrap_or(config_file.current_profile());
+            let mut config = config_file.profiles.get(name).cloned().unwrap_or_else(|| Config {
+                api_url: DEFAULT_API_URL.to_string(),
+                ..Config::default()
+            });
+            config.profile = name.to_string();
+            config.api_token.clear();
+            if let Some(url) = std::env::var("MOYN_API_URL").ok().filter(|url| !url.is_empty()) {
+                config.api_url = url.trim_end_matches('/').to_string();
+            }
+            Ok(config)
+        }
+        result => result,
+    }
+}
+
+fn validate_profile_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(Error::Validation(format!(
+            "Invalid profile name '{}'. Use letters, digits, '-' and '_'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn login(profile: Option<&str>, token_stdin: bool, url: Option<String>, no_keyring: bool) -> Result<(), Error> {
+    let mut config_file = load_config_file()?;
+    let name = profile.unwrap_or(config_file.current_profile()).to_string();
+    validate_profile_name(&name)?;
+
+    if !token_stdin {
+        print!("Enter your API token (from your profile page): ");
+        io::stdout().flush().unwrap();
+    }
+
+    // Don't echo the token when typed or pasted into a terminal
+    let token = if token_stdin {
+        let mut token = String::new();
+        io::stdin()
+            .read_to_string(&mut token)
+            .map_err(|e| format!("Could not read token from stdin: {}", e))?;
+        token
+    } else if io::stdin().is_terminal() {
+        rpassword::read_password().map_err(|e| format!("Could not read input: {}", e))?
+    } else {
+        let mut token = String::new();
+        io::stdin()
+            .read_line(&mut token)
+            .map_err(|e| format!("Could not read input: {}", e))?;
+        token
+    };
+    let token = token.trim().to_string();
+
+    if !token.starts_with("moyn_") {
+        return Err(Error::Validation(
+            "Invalid token format. Token should start with 'moyn_'".to_string(),
+        ));
+    }
+
+    let url = match url {
+        Some(url) => url,
+        None if token_stdin => DEFAULT_API_URL.to_string(),
+        None => {
+            print!("Enter API URL [{}]: ", DEFAULT_API_URL);
+            io::stdout().flush().unwrap();
+
+            let mut url = String::new();
+            io::stdin()
+                .read_line(&mut url)
+                .map_err(|e| format!("Could not read input: {}", e))?;
+            let url = url.trim();
+            if url.is_empty() {
+                DEFAULT_API_URL.to_string()
+            } else {
+                url.to_string()
+            }
+        }
+    };
+
+    // Logging in again keeps the profile's connection settings
+    let mut config = config_file.profiles.get(&name).cloned().unwrap_or_default();
+    config.profile = name.clone();
+    config.api_token = token;
+    config.api_url = url.trim_end_matches('/').to_string();
+
+    let me = fetch_me(&new_client(config.clone())?)?;
+
+    let stored = if no_keyring {
+        Err("disabled with --no-keyring".to_string())
+    } else {
+        store_token(&name, &config.api_token).map_err(|e| e.to_string())
+    };
+    match stored {
+        Ok(()) => config.api_token.clear(),
+        Err(e) => {
+            delete_token(&name);
+            eprintln!("Not using the system keyring ({}).", e);
+            eprintln!("The token is stored in {}, readable only by you.", config_path().display());
+        }
+    }
+
+    config_file.profiles.insert(name.clone(), config);
+    if config_file.current.is_none() {
+        config_file.current = Some(name.clone());
+    }
+    save_config_file(&config_file)?;
+
+    println!(
+        "Logged in as {} on {} (profile: {})",
+        me.user.display_name(),
+        config_file.profiles[&name].api_url,
+        name
+    );
+    Ok(())
+}
+
+fn whoami(client: &MoynClient, output: OutputFormat) -> Result<(), Error> {
+    let me = fetch_me(client)?;
+    let identity = Identity {
+        user: me.user,
+        instance: client.config().api_url.clone(),
+        profile: client.config().profile.clone(),
+        scopes: me.scopes,
+    };
+
+    if output != OutputFormat::Table {
+        return print_item(output, &identity);
+    }
+
+    println!("User:     {}", identity.user.display_name());
+    println!("Instance: {}", identity.instance);
+    println!("Profile:  {}", identity.profile);
+    if !identity.scopes.is_empty() {
+        println!("Scopes:   {}", identity.scopes.join(", "));
+    }
+    Ok(())
+}
+
+fn profile_list() -> Result<(), Error> {
+    let config_file = load_config_file()?;
+
+    if config_file.profiles.is_empty() {
+        println!("No profiles yet. Create one with `moyn login`");
+        return Ok(());
+    }
+
+    println!("  {:<20} URL", "PROFILE");
+    for (name, config) in &config_file.profiles {
+        let marker = if name == config_file.current_profile() { "*" } else { " " };
+        println!("{} {:<20} {}", marker, name, config.api_url);
+    }
+    Ok(())
+}
+
+fn profile_use(name: String) -> Result<(), Error> {
+    let mut config_file = load_config_file()?;
+
+    if !config_file.profiles.contains_key(&name) {
+        return Err(Error::Config(format!(
+            "Profile '{}' not found. Run `moyn login --profile {}` to create it.",
+            name, name
+        )));
+    }
+
+    config_file.current = Some(name.clone());
+    save_config_file(&config_file)?;
+    println!("Now using profile '{}'.", name);
+    Ok(())
+}
+
+fn profile_remove(name: String) -> Result<(), Error> {
+    let mut config_file = load_config_file()?;
+
+    if config_file.profiles.remove(&name).is_none() {
+        return Err(Error::NotFound(format!("Profile '{}' not found.", name)));
+    }
+    delete_token(&name);
+
+    if config_file.current.as_deref() == Some(name.as_str()) {
+        config_file.current = None;
+    }
+    save_config_file(&config_file)?;
+
+    println!("Removed profile '{}'.", name);
+    if config_file.current.is_none() && !config_file.profiles.is_empty() {
+        println!("Choose the profile to use with `moyn profile use <NAME>`");
+    }
+    Ok(())
+}
+
+/// Turn a title into a URL slug: lowercase words joined by hyphens
+fn slugify(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn load_template(name: &str) -> Result<String, Error> {
+    let path = templates_dir().join(format!("{}.md", name));
+    match fs::read_to_string(&path) {
+        Ok(template) => Ok(template),
+        Err(e) if e.kind() == io::ErrorKind::NotFound && name == "default" => Ok(String::new()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(Error::NotFound(format!("Template '{}' not found. Add it as {}", name, path.display())))
+        }
+        Err(e) => Err(Error::Other(format!("Could not read template {}: {}", path.display(), e))),
+    }
+}
+
+/// Fill in the `{{name}}` placeholders of a template. In the frontmatter the
+/// values are inserted as YAML scalars, so a title like `Rust: again` stays a
+/// single string.
+fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    let fill = |text: &str, yaml: bool| {
+        values.iter().fold(text.to_string(), |text, (name, value)| {
+            let value = if yaml { yaml_scalar(value) } else { value.to_string() };
+            text.replace(&format!("{{{{{}}}}}", name), &value)
+        })
+    };
+
+    match find_frontmatter(template) {
+        Some(block) => format!(
+            "{}{}{}",
+            &template[..block.yaml.start],
+            fill(&template[block.yaml.clone()], true),
+            fill(&template[block.yaml.end..], false)
+        ),
+        None => fill(template, false),
+    }
+}
+
+fn open_in_editor(file: &Path) -> Result<(), Error> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // $EDITOR may carry arguments, e.g. `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or_else(|| Error::Config("$EDITOR is empty".to_string()))?;
+
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(file)
+        .status()
+        .map_err(|e| format!("Could not start editor '{}': {}", editor, e))?;
+
+    if !status.success() {
+        return Err(Error::Other(format!("Editor '{}' exited with {}", editor, status)));
+    }
+    Ok(())
+}
+
+fn new_post(args: NewArgs, profile: Option<&str>) -> Result<(), Error> {
+    let NewArgs { title, slug, tags, space, template, dir, no_edit } = args;
+
+    let slug = slug.unwrap_or_else(|| slugify(&title));
+    if slug.is_empty() {
+        return Err(Error::Validation(
+            "Could not derive a slug from the title. Pass one with --slug".to_string(),
+        ));
+    }
+
+    let file = dir.join(format!("{}.md", slug));
+    if file.exists() {
+        return Err(Error::Validation(format!("{} already exists", file.display())));
+    }
+
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let mut content = render_template(
+        &load_template(&template)?,
+        &[
+            ("title", &title),
+            ("slug", &slug),
+            ("space", space.as_deref().unwrap_or_default()),
+            ("date", &date),
+        ],
+    );
+
+    let template_frontmatter = parse_frontmatter(&content, false)
+        .map_err(|e| Error::Validation(e.at(&templates_dir().join(format!("{}.md", template)))))?
+        .frontmatter;
+
+    let mut fields = vec![("title", yaml_scalar(&title)), ("slug", yaml_scalar(&slug))];
+    // Keep the template's default tags unless others are given
+    if !tags.is_empty() || template_frontmatter.tags.is_none() {
+        fields.push(("tags", yaml_list(&tags)));
+    }
+    fields.push(("published", "false".to_string()));
+    match &space {
+        Some(space) => fields.push(("space", yaml_scalar(space))),
+        // A `space: {{space}}` template line without --space would be left empty
+        None if template_frontmatter.space.as_deref().is_some_and(|space| space.trim().is_empty()) => {
+            content = remove_frontmatter_fields(&content, &["space"]);
+        }
+        None => {}
+    }
+
+    fs::create_dir_all(&dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    fs::write(&file, set_frontmatter_fields(&content, &fields))
+        .map_err(|e| format!("Could not write {}: {}", file.display(), e))?;
+    println!("Created {}", file.display());
+
+    if no_edit {
+        return Ok(());
+    }
+
+    open_in_editor(&file)?;
+
+    let draft = fs::read_to_string(&file)
+        .ok()
+        .and_then(|content| parse_frontmatter(&content, true).ok())
+        .is_some_and(|parsed| parsed.frontmatter.published == Some(false));
+
+    if draft {
+        print!("Upload {} as a draft now? [y/N] ", file.display());
+    } else {
+        print!("Publish {} now? [y/N] ", file.display());
+    }
+    io::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| format!("Could not read input: {}", e))?;
+
+    if answer.trim().eq_ignore_ascii_case("y") {
+        let args = PublishArgs {
+            file,
+            new: false,
+            write_back: false,
+            dry_run: false,
+            watch: false,
+            content: ContentArgs { raw: false, lenient: false, no_upload: false },
+        };
+        publish(&connect(profile)?, args, OutputFormat::Table)
+    } else {
+        println!("Publish it later with `moyn publish {}`", file.display());
+        Ok(())
+    }
+}
+
+fn extract_title(content: &str, filename: &str) -> String {
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if let Some(heading) = trimmed.strip_prefix("# ") {
+            return heading.trim().to_string();
+        }
+    }
+    // Fallback to filename without extension
+    PathBuf::from(filename)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Untitled")
+        .to_string()
+}
+
+/// A markdown file turned into the request that publishing it would send.
+struct PreparedPost {
+    raw_content: String,
+    post: CreatePost,
+    id: Option<u64>,
+    space: Option<String>,
+    published_at: Option<String>,
+    /// Local files referenced from the content, uploaded before publishing
+    assets: Vec<Asset>,
+    /// Hash of everything sent to the server, used to detect changes
+    hash: String,
+}
+
+/// A local file linked from a post
+struct Asset {
+    /// Where the link target sits in the post content
+    range: Range<usize>,
+    path: PathBuf,
+    hash: String,
+}
+
+/// Find link and image targets in markdown, plus `src` attributes of inline
+/// HTML, skipping fenced code blocks. Returns the byte range of each target.
+fn link_targets(content: &str) -> Vec<Range<usize>> {
+    let mut targets = Vec::new();
+    let mut in_fence = false;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        for (i, _) in line.match_indices("](") {
+            let rest = &line[i + 2..];
+            let (skip, end) = match rest.strip_prefix('<') {
+                Some(inner) => (1, inner.find('>')),
+                None => (0, rest.find(|c: char| c == ')' || c.is_whitespace())),
+            };
+            if let Some(end) = end {
+                let target = start + i + 2 + skip;
+                targets.push(target..target + end);
+            }
+        }
+
+        for (i, _) in line.match_indices("src=\"") {
+            let rest = &line[i + 5..];
+            if let Some(end) = rest.find('"') {
+                let target = start + i + 5;
+                targets.push(target..target + end);
+            }
+        }
+    }
+
+    targets.retain(|range| !range.is_empty());
+    targets.sort_by_key(|range| range.start);
+    targets
+}
+
+/// Local files linked from `content`, resolved relative to the markdown file.
+/// Links to other markdown files are left alone.
+fn local_assets(file: &Path, content: &str) -> Result<Vec<Asset>, Error> {
+    let base = file.parent().unwrap_or(Path::new("."));
+    let mut assets = Vec::new();
+
+    for range in link_targets(content) {
+        let target = &content[range.clone()];
+        // Skip URLs (`https:`, `mailto:`, `data:`...), anchors and absolute paths
+        if target.contains(':') || target.starts_with(['#', '/']) {
+            continue;
+        }
+
+        let path = base.join(target);
+        if !path.is_file() || path.extension().is_some_and(|ext| ext == "md") {
+            continue;
+        }
+
+        let bytes = fs::read(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        assets.push(Asset {
+            range,
+            path,
+            hash: format!("{:x}", Sha256::digest(&bytes)),
+        });
+    }
+
+    Ok(assets)
+}
+
+/// Read and parse a markdown file. With `--raw`, the whole document is sent
+/// as the post content instead of just the body after the frontmatter.
+fn prepare_post(file: &Path, options: ContentArgs) -> Result<PreparedPost, Error> {
+    let raw_content = fs::read_to_string(file).map_err(|e| {
+        let message = format!("Could not read {}: {}", file.display(), e);
+        if e.kind() == io::ErrorKind::NotFound {
+            Error::NotFound(message)
+        } else {
+            Error::Other(message)
+        }
+    })?;
+
+    let parsed = parse_frontmatter(&raw_content, options.lenient).map_err(|e| Error::Validation(e.at(file)))?;
+    for warning in &parsed.warnings {
+        eprintln!("Warning: {}", warning.at(file));
+    }
+
+    // Use frontmatter title, or fall back to heading/filename extraction
+    let title = parsed.frontmatter.title
+        .unwrap_or_else(|| extract_title(&parsed.content, file.to_str().unwrap_or("post")));
+
+    // Use frontmatter published value, or default to true
+    let published = parsed.frontmatter.published.unwrap_or(true);
+
+    let post = CreatePost {
+        title,
+        content: if options.raw { raw_content.clone() } else { parsed.content },
+        published,
+        slug: parsed.frontmatter.slug,
+        tags: parsed.frontmatter.tags,
+    };
+
+    let assets = if options.no_upload {
+        Vec::new()
+    } else {
+        local_assets(file, &post.content)?
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(parsed.frontmatter.space.as_deref().unwrap_or_default());
+    hasher.update(serde_json::to_vec(&PostRequest { post: &post }).map_err(|e| format!("Could not serialize post: {}", e))?);
+    for asset in &assets {
+        hasher.update(&asset.hash);
+    }
+
+    Ok(PreparedPost {
+        raw_content,
+        post,
+        id: parsed.frontmatter.id,
+        // An empty `space:` means no space, not a space with an empty slug
+        space: parsed.frontmatter.space.filter(|space| !space.trim().is_empty()),
+        published_at: parsed.frontmatter.published_at,
+        assets,
+        hash: format!("{:x}", hasher.finalize()),
+    })
+}
+
+/// Upload the post's local assets, reusing earlier uploads of the same
+/// content, and point the links at the uploaded URLs.
+fn upload_assets(client: &MoynClient, state: &mut State, prepared: &mut PreparedPost) -> Result<(), Error> {
+    // Replace from the end so earlier ranges stay valid
+    for asset in prepared.assets.iter().rev() {
+        let url = match state.media.get(&asset.hash) {
+            Some(url) => url.clone(),
+            None => {
+                let url = client
+                    .upload_media(&asset.path)
+                    .map_err(|e| e.context(format!("Failed to upload {}", asset.path.display())))?;
+                eprintln!("Uploaded: {}", asset.path.display());
+                state.media.insert(asset.hash.clone(), url.clone());
+                url
+            }
+        };
+        prepared.post.content.replace_range(asset.range.clone(), &url);
+    }
+    prepared.assets.clear();
+    Ok(())
+}
+
+/// Where the ID of an already published post was found.
+enum ExistingPost {
+    Frontmatter(u64),
+    State(u64),
+    Slug(u64),
+}
+
+impl ExistingPost {
+    fn id(&self) -> u64 {
+        match self {
+            ExistingPost::Frontmatter(id) | ExistingPost::State(id) | ExistingPost::Slug(id) => *id,
+        }
+    }
+}
+
+/// Look up a previously published post for this file: by frontmatter `id`,
+/// then the local state file, then by matching `slug` against `server_posts`.
+fn find_existing_post(
+    state: &State,
+    file: &Path,
+    prepared: &PreparedPost,
+    server_posts: impl FnOnce() -> Result<Vec<Post>, Error>,
+) -> Result<Option<ExistingPost>, Error> {
+    if let Some(id) = prepared.id {
+        return Ok(Some(ExistingPost::Frontmatter(id)));
+    }
+
+    if let Some(published) = state.posts.get(&state_key(file)) {
+        return Ok(Some(ExistingPost::State(published.id)));
+    }
+
+    let Some(slug) = &prepared.post.slug else {
+        return Ok(None);
+    };
+
+    Ok(server_posts()?
+        .into_iter()
+        .find(|post| &post.slug == slug)
+        .map(|post| ExistingPost::Slug(post.id)))
+}
+
+fn publish(client: &MoynClient, args: PublishArgs, output: OutputFormat) -> Result<(), Error> {
+    let PublishArgs { file, new, write_back, watch, content: options, .. } = args;
+
+    if watch {
+        return watch_and_publish(client, &file, write_back, options, output);
+    }
+    let prepared = prepare_file(&file, options)?;
+    publish_prepared(client, &file, prepared, new, write_back, output)
+}
+
+/// `publish --dry-run`, which needs no login since nothing is sent
+fn publish_dry_run(config: &Config, args: PublishArgs) -> Result<(), Error> {
+    let prepared = prepare_file(&args.file, args.content)?;
+    print_dry_run(config, &args.file, &prepared, args.new)
+}
+
+/// Prepare a single file for publishing, pointing directories to --watch and sync.
+fn prepare_file(file: &Path, options: ContentArgs) -> Result<PreparedPost, Error> {
+    if file.is_dir() {
+        return Err(Error::Validation(format!(
+            "{} is a directory. Use --watch to watch it, or `moyn sync` to publish it.",
+            file.display()
+        )));
+    }
+    prepare_post(file, options)
+}
+
+fn print_dry_run(config: &Config, file: &Path, prepared: &PreparedPost, new: bool) -> Result<(), Error> {
+    let state = load_state(config)?;
+
+    let existing = if new {
+        None
+    } else {
+        // Don't contact the server to look the slug up
+        find_existing_post(&state, file, prepared, || Ok(Vec::new()))?
+    };
+
+    let api_url = &config.api_url;
+    let (method, endpoint) = match (&existing, &prepared.space) {
+        (Some(existing), _) => ("PATCH", format!("{}/api/v1/posts/{}", api_url, existing.id())),
+        (None, Some(space)) => ("POST", format!("{}/api/v1/spaces/{}/posts", api_url, space)),
+        (None, None) => ("POST", format!("{}/api/v1/posts", api_url)),
+    };
+    let body = serde_json::to_string_pretty(&PostRequest { post: &prepared.post })
+        .map_err(|e| format!("Could not serialize post: {}", e))?;
+
+    let mut seen = BTreeSet::new();
+    for asset in prepared.assets.iter().filter(|asset| seen.insert(&asset.hash)) {
+        match state.media.get(&asset.hash) {
+            Some(url) => println!("Reuse upload: {} -> {}", asset.path.display(), url),
+            None => println!("Upload: {}", asset.path.display()),
+        }
+    }
+    if !prepared.assets.is_empty() {
+        println!();
+    }
+
+    println!("{} {}", method, endpoint);
+    println!("{}", body);
+
+    if existing.is_none() && !new && let Some(slug) = &prepared.post.slug {
+        println!("\nNote: the server was not checked for an existing post with slug '{}'.", slug);
+        println!("If one exists, it will be updated instead.");
+    }
+    println!("\nDry run: nothing was sent.");
+    Ok(())
+}
+
+/// Create or update the post for a file and record it in the state file.
+fn publish_prepared(
+    client: &MoynClient,
+    file: &Path,
+    mut prepared: PreparedPost,
+    new: bool,
+    write_back: bool,
+    output: OutputFormat,
+) -> Result<(), Error> {
+    let mut state = load_state(client.config())?;
+
+    let existing = if new {
+        None
+    } else {
+        find_existing_post(&state, file, &prepared, || client.list_posts(prepared.space.as_deref()))?
+    };
+
+    let uploaded = upload_assets(client, &mut state, &mut prepared);
+    // Keep the record of whatever was uploaded, even if a later upload failed
+    save_state(client.config(), &state)?;
+    uploaded?;
+
+    let mut updated = None;
+    if let Some(existing) = &existing {
+        updated = match client.update_post(existing.id(), &prepared.post) {
+            Ok(post) => Some(post),
+            Err(moyn::Error::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        if updated.is_none() {
+            match existing {
+                // The post was deleted on the server; forget it and publish afresh
+                ExistingPost::State(_) => {
+                    state.posts.remove(&state_key(file));
+                }
+                _ => {
+                    return Err(Error::NotFound(format!(
+                        "Post {} not found. Remove `id:` from the frontmatter or use --new to publish it as a new post.",
+                        existing.id()
+                    )));
+                }
+            }
+        }
+    }
+
+    let (post, action) = match updated {
+        Some(post) => (post, "Updated"),
+        None => (client.create_post(prepared.space.as_deref(), &prepared.post)?, "Published"),
+    };
+
+    state.posts.insert(
+        state_key(file),
+        PublishedFile {
+            id: post.id,
+            hash: Some(prepared.hash.clone()),
+        },
+    );
+    save_state(client.config(), &state)?;
+
+    if write_back {
+        write_back_frontmatter(file, &prepared, &post)?;
+    }
+
+    if output != OutputFormat::Table {
+        return print_item(output, &post);
+    }
+
+    println!("{}: {}", action, post.title);
+    println!("URL: {}", post.url);
+    Ok(())
+}
+
+/// How long a file has to stay quiet before it is republished, so a burst of
+/// writes from one save results in a single update.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Republish a file, or any markdown file in a directory, whenever it is
+/// saved. Errors are reported and the watcher keeps running.
+fn watch_and_publish(
+    client: &MoynClient,
+    target: &Path,
+    write_back: bool,
+    options: ContentArgs,
+    output: OutputFormat,
+) -> Result<(), Error> {
+    let target = fs::canonicalize(target).map_err(|e| format!("Could not watch {}: {}", target.display(), e))?;
+    let is_dir = target.is_dir();
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| format!("Could not start watcher: {}", e))?;
+
+    // Editors often save by replacing the file, so watch its directory
+    let (watch_path, mode) = if is_dir {
+        (target.as_path(), RecursiveMode::Recursive)
+    } else {
+        (target.parent().unwrap_or(&target), RecursiveMode::NonRecursive)
+    };
+    watcher
+        .watch(watch_path, mode)
+        .map_err(|e| format!("Could not watch {}: {}", watch_path.display(), e))?;
+
+    eprintln!("Watching {} for changes (Ctrl-C to stop)", target.display());
+
+    let is_relevant = |path: &Path| {
+        if is_dir {
+            path.extension().is_some_and(|ext| ext == "md")
+                && !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        } else {
+            path == target
+        }
+    };
+
+    while let Ok(event) = rx.recv() {
+        let mut changed = BTreeSet::new();
+        let mut collect = |event: notify::Result<notify::Event>| match event {
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                changed.extend(event.paths.into_iter().filter(|path| is_relevant(path)));
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Error: {}", e),
+        };
+
+        collect(event);
+        while let Ok(event) = rx.recv_timeout(WATCH_DEBOUNCE) {
+            collect(event);
+        }
+
+        for file in changed {
+            if !file.is_file() {
+                continue;
+            }
+            if let Err(e) = republish_if_changed(client, &file, write_back, options, output) {
+                eprintln!("Error: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn republish_if_changed(
+    client: &MoynClient,
+    file: &Path,
+    write_back: bool,
+    options: ContentArgs,
+    output: OutputFormat,
+) -> Result<(), Error> {
+    let prepared = prepare_post(file, options)?;
+
+    // Saves that don't change what would be sent, including our own
+    // --write-back edits, are skipped
+    let unchanged = load_state(client.config())?
+        .posts
+        .get(&state_key(file))
+        .is_some_and(|published| published.hash.as_ref() == Some(&prepared.hash));
+    if unchanged {
+        return Ok(());
+    }
+
+    eprintln!("[{}] {}", chrono::Local::now().format("%H:%M:%S"), file.display());
+    publish_prepared(client, file, prepared, false, write_back, output)
+}
+
+fn write_back_frontmatter(file: &Path, prepared: &PreparedPost, post: &Post) -> Result<(), Error> {
+    let mut fields = vec![("id", post.id.to_string()), ("url", yaml_scalar(&post.url))];
+
+    // Keep the original publication time across updates
+    if prepared.post.published {
+        let published_at = post
+            .published_at
+            .clone()
+            .or_else(|| prepared.published_at.clone())
+            .unwrap_or_else(|| chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+        fields.push(("published_at", yaml_scalar(&published_at)));
+    }
+
+    fs::write(file, set_frontmatter_fields(&prepared.raw_content, &fields))
+        .map_err(|e| Error::Other(format!("Could not update frontmatter: {}", e)))
+}
+
+/// Recursively collect markdown files, skipping hidden files and directories.
+fn markdown_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let entries = fs::read_dir(dir).map_err(|e| format!("Could not read {}: {}", dir.display(), e))?;
+
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Could not read {}: {}", dir.display(), e))?.path();
+        if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(markdown_files(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// If `key` has no state entry but a file that is gone from `root` was
+/// published as post `id`, that file was renamed or moved to `key`. Move its
+/// entry over and return the old key.
+fn follow_rename(state: &mut State, root: &str, key: &str, id: u64) -> Option<String> {
+    if state.posts.contains_key(key) {
+        return None;
+    }
+    let old_key = state
+        .posts
+        .iter()
+        .find(|(old, published)| {
+            published.id == id && Path::new(old).starts_with(root) && !Path::new(old).exists()
+        })
+        .map(|(old, _)| old.clone())?;
+
+    let published = state.posts.remove(&old_key)?;
+    state.posts.insert(key.to_string(), published);
+    Some(old_key)
+}
+
+enum SyncAction {
+    Create { file: PathBuf, prepared: PreparedPost },
+    Update { file: PathBuf, prepared: PreparedPost, id: u64 },
+    Delete { key: String, id: u64 },
+    Unchanged,
+    Skip { file: PathBuf, reason: String },
+}
+
+fn sync(client: &MoynClient, dir: PathBuf, delete: bool, dry_run: bool, options: ContentArgs) -> Result<(), Error> {
+    let mut state = load_state(client.config())?;
+
+    if !dir.is_dir() {
+        return Err(Error::Validation(format!("{} is not a directory", dir.display())));
+    }
+    let root = state_key(&dir);
+
+    // Everything on the server, across the profile and all spaces
+    let mut server_posts: Vec<(Option<String>, Post)> = client.list_posts(None)?
+        .into_iter()
+        .map(|post| (None, post))
+        .collect();
+    for space in client.list_spaces()? {
+        for post in client.list_posts(Some(&space.slug))? {
+            server_posts.push((Some(space.slug.clone()), post));
+        }
+    }
+    let on_server = |id: u64| server_posts.iter().any(|(_, post)| post.id == id);
+
+    let mut actions = Vec::new();
+    // Posts that a current file resolves to, and files that were renamed since the last sync
+    let mut in_use = BTreeSet::new();
+    let mut renamed = Vec::new();
+    for file in markdown_files(&dir)? {
+        let prepared = match prepare_post(&file, options) {
+            Ok(prepared) => prepared,
+            Err(reason) => {
+                actions.push(SyncAction::Skip { file, reason: reason.to_string() });
+                continue;
+            }
+        };
+
+        let existing = find_existing_post(&state, &file, &prepared, || {
+            Ok(server_posts
+                .iter()
+                .filter(|(space, _)| space == &prepared.space)
+                .map(|(_, post)| post.clone())
+                .collect())
+        })?;
+
+        let action = match existing {
+            None => SyncAction::Create { file, prepared },
+            Some(ExistingPost::Frontmatter(id)) if !on_server(id) => SyncAction::Skip {
+                file,
+                reason: format!("post {} from frontmatter `id:` not found", id),
+            },
+            Some(ExistingPost::State(id)) if !on_server(id) => SyncAction::Create { file, prepared },
+            Some(existing) => {
+                let id = existing.id();
+                in_use.insert(id);
+                if let Some(old_key) = follow_rename(&mut state, &root, &state_key(&file), id) {
+                    renamed.push((old_key, file.clone(), id));
+                }
+                let unchanged = state
+                    .posts
+                    .get(&state_key(&file))
+                    .is_some_and(|published| published.id == id && published.hash.as_ref() == Some(&prepared.hash));
+                if unchanged {
+                    SyncAction::Unchanged
+                } else {
+                    SyncAction::Update { file, prepared, id }
+                }
+            }
+        };
+        actions.push(action);
+    }
+
+    // Files that were synced from this directory before but no longer exist,
+    // unless their post lives on in another file
+    for (key, published) in &state.posts {
+        if Path::new(key).starts_with(&root)
+            && !Path::new(key).exists()
+            && on_server(published.id)
+            && !in_use.contains(&published.id)
+        {
+            actions.push(SyncAction::Delete {
+                key: key.clone(),
+                id: published.id,
+            });
+        }
+    }
+
+    let display = |path: &Path| path.strip_prefix(&dir).unwrap_or(path).display().to_string();
+    let mut unchanged = 0;
+    let mut changes = 0;
+    let mut skipped = 0;
+
+    println!("Plan for {}:", dir.display());
+    for (old_key, file, id) in &renamed {
+        println!("  rename  {} -> {} (post {})", display(Path::new(old_key)), display(file), id);
+    }
+    for action in &actions {
+        match action {
+            SyncAction::Create { file, .. } => println!("  create  {}", display(file)),
+            SyncAction::Update { file, id, .. } => println!("  update  {} (post {})", display(file), id),
+            SyncAction::Delete { key, id } if delete => println!("  delete  post {} ({})", id, key),
+            SyncAction::Delete { key, id } => {
+                println!("  keep    post {} ({} was removed; pass --delete to delete it)", id, key)
+            }
+            SyncAction::Skip { file, reason } => {
+                println!("  skip    {}: {}", display(file), reason);
+                skipped += 1;
+            }
+            SyncAction::Unchanged => unchanged += 1,
+        }
+        if matches!(action, SyncAction::Create { .. } | SyncAction::Update { .. })
+            || (delete && matches!(action, SyncAction::Delete { .. }))
+        {
+            changes += 1;
+        }
+    }
+    if unchanged > 0 {
+        println!("  {} unchanged", unchanged);
+    }
+
+    // Skipped files fail the sync, so CI doesn't silently drop malformed posts
+    let skipped_error = || Error::Validation(format!("Skipped {} file(s) that could not be published", skipped));
+
+    if changes == 0 {
+        if !dry_run && !renamed.is_empty() {
+            save_state(client.config(), &state)?;
+        }
+        if skipped > 0 {
+            return Err(skipped_error());
+        }
+        println!("\nEverything is up to date.");
         return Ok(());
     }
+    if dry_run {
+        println!("\nDry run: nothing was changed.");
+        return if skipped > 0 { Err(skipped_error()) } else { Ok(()) };
+    }
+
+    println!();
+    let mut failures = 0;
+    for action in actions {
+        let result = match action {
+            SyncAction::Create { file, mut prepared } => upload_assets(client, &mut state, &mut prepared)
+                .and_then(|()| client.create_post(prepared.space.as_deref(), &prepared.post))
+                .map(|post| {
+                    println!("Published: {} ({})", display(&file), post.url);
+                    state.posts.insert(state_key(&file), PublishedFile { id: post.id, hash: Some(prepared.hash) });
+                })
+                .map_err(|e| e.context(display(&file))),
+            SyncAction::Update { file, mut prepared, id } => upload_assets(client, &mut state, &mut prepared)
+                .and_then(|()| client.update_post(id, &prepared.post))
+                .map(|post| {
+                    println!("Updated: {} ({})", display(&file), post.url);
+                    state.posts.insert(state_key(&file), PublishedFile { id: post.id, hash: Some(prepared.hash) });
+                })
+                .map_err(|e| e.context(display(&file))),
+            SyncAction::Delete { key, id } if delete => client.delete_post(id).map(|()| {
+                println!("Deleted: post {} ({})", id, key);
+                state.posts.remove(&key);
+            }),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            failures += 1;
+        }
+        save_state(client.config(), &state)?;
+    }
+
+    if failures > 0 {
+        return Err(Error::Other(format!("{} of {} changes failed", failures, changes)));
+    }
+    if skipped > 0 {
+        return Err(skipped_error());
+    }
+    Ok(())
+}
+
+/// Render a post as a markdown file with frontmatter that `publish` reads back
+/// to the same post.
+fn post_to_markdown(post: &Post, space: Option<&str>) -> String {
+    let mut fields = vec![
+        ("id", post.id.to_string()),
+        ("title", yaml_scalar(&post.title)),
+        ("slug", yaml_scalar(&post.slug)),
+    ];
+    if !post.tags.is_empty() {
+        fields.push(("tags", yaml_list(&post.tags)));
+    }
+    if let Some(space) = space {
+        fields.push(("space", yaml_scalar(space)));
+    }
+    if let Some(published) = post.published {
+        fields.push(("published", published.to_string()));
+    }
+    fields.push(("url", yaml_scalar(&post.url)));
+    if let Some(published_at) = &post.published_at {
+        fields.push(("published_at", yaml_scalar(published_at)));
+    }
+
+    set_frontmatter_fields(post.content.as_deref().unwrap_or_default(), &fields)
+}
+
+fn pull(client: &MoynClient, dir: PathBuf, space: Option<String>, force: bool) -> Result<(), Error> {
+    let mut state = load_state(client.config())?;
+    let mut unsafe_names = 0;
+
+    // Without --space, pull everything: profile posts into `dir`, and the
+    // posts of each space into a subdirectory named after it
+    let mut sources = vec![(space.clone(), dir.clone())];
+    if space.is_none() {
+        for space in client.spaces() {
+            let space = space?;
+            if !is_safe_file_name(&space.slug) {
+                eprintln!("Skipped: space '{}' (its slug can't be used as a directory name)", space.slug);
+                unsafe_names += 1;
+                continue;
+            }
+            let space_dir = dir.join(&space.slug);
+            sources.push((Some(space.slug), space_dir));
+        }
+    }
+
+    let mut posts = Vec::new();
+    for (space, dir) in sources {
+        for post in client.list_posts(space.as_deref())? {
+            posts.push((space.clone(), dir.clone(), post));
+        }
+    }
+    if posts.is_empty() && unsafe_names == 0 {
+        println!("No posts to pull.");
+        return Ok(());
+    }
+
+    let mut pulled = 0;
+    for (space, post_dir, post) in posts {
+        if !is_safe_file_name(&post.slug) {
+            eprintln!("Skipped: post {} (its slug '{}' can't be used as a file name)", post.id, post.slug);
+            unsafe_names += 1;
+            continue;
+        }
+
+        let file = post_dir.join(format!("{}.md", post.slug));
+        if file.exists() && !force {
+            println!("Skipped: {} (already exists, use --force to overwrite)", file.display());
+            continue;
+        }
+
+        // Listings may leave out the body
+        let post = match post.content {
+            Some(_) => post,
+            None => client.get_post(post.id)?,
+        };
+        let post_space = space.as_deref().or(post.space.as_deref());
+
+        fs::create_dir_all(&post_dir).map_err(|e| format!("Could not create {}: {}", post_dir.display(), e))?;
+        fs::write(&file, post_to_markdown(&post, post_space))
+            .map_err(|e| format!("Could not write {}: {}", file.display(), e))?;
+
+        // Give the file the post's modification time, so it sorts like on the server
+        if let Some(updated_at) = post.updated_at.as_deref().and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            && let Ok(handle) = fs::File::options().write(true).open(&file)
+        {
+            let _ = handle.set_modified(updated_at.into());
+        }
+
+        // Record the file as published, so it isn't seen as changed until edited
+        let hash = prepare_post(&file, ContentArgs { raw: false, lenient: true, no_upload: false })
+            .ok()
+            .map(|prepared| prepared.hash);
+        state.posts.insert(state_key(&file), PublishedFile { id: post.id, hash });
+
+        println!("Pulled: {}", file.display());
+        pulled += 1;
+    }
+
+    save_state(client.config(), &state)?;
+    println!("\n{} post(s) pulled to {}", pulled, dir.display());
+
+    if unsafe_names > 0 {
+        return Err(Error::Validation(format!(
+            "Skipped {} post(s) or space(s) whose slug is not a safe file name",
+            unsafe_names
+        )));
+    }
+    Ok(())
+}
+
+/// Whether a slug from the server can be used as a file or directory name
+/// without escaping the target directory or becoming a hidden file.
+fn is_safe_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']) && !name.contains("..")
+}
+
+fn posts(client: &MoynClient, args: PostsArgs, output: OutputFormat) -> Result<(), Error> {
+    let PostsArgs { space, filter, sort, list } = args;
+
+    let matching = client
+        .posts(space.as_deref())
+        .filter(|post| post.as_ref().map_or(true, |post| filter.matches(post)));
+
+    let Some(sort) = sort else {
+        return print_list(output, matching, list.limit(output));
+    };
+    let mut posts = matching.collect::<Result<Vec<_>, _>>()?;
+    sort_posts(&mut posts, sort);
+    print_list(output, posts.into_iter().map(Ok), list.limit(output))
+}
+
+impl PostFilter {
+    fn matches(&self, post: &Post) -> bool {
+        if !self.tags.iter().all(|tag| post.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))) {
+            return false;
+        }
+
+        if (self.published || self.drafts) && is_published(post) != self.published {
+            return false;
+        }
+
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        let Some(date) = post_time(post).map(|time| time.date_naive()) else {
+            return false;
+        };
+        self.since.is_none_or(|since| date >= since) && self.until.is_none_or(|until| date <= until)
+    }
+}
+
+/// Whether a post is published. Servers that leave out `published` are
+/// taken at their `published_at`.
+fn is_published(post: &Post) -> bool {
+    post.published.unwrap_or(post.published_at.is_some())
+}
+
+/// When a post was published, or created if it is a draft
+fn post_time(post: &Post) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    parse_time(post.published_at.as_deref().or(post.created_at.as_deref()))
+}
+
+fn parse_time(time: Option<&str>) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    time.and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+}
+
+/// Sort posts in place. Posts without the date to sort by go last.
+fn sort_posts(posts: &mut [Post], sort: PostSort) {
+    use std::cmp::Reverse;
+
+    match sort {
+        PostSort::Newest => posts.sort_by_key(|post| (post_time(post).is_none(), Reverse(post_time(post)))),
+        PostSort::Oldest => posts.sort_by_key(|post| (post_time(post).is_none(), post_time(post))),
+        PostSort::Updated => posts.sort_by_key(|post| {
+            let updated = parse_time(post.updated_at.as_deref());
+            (updated.is_none(), Reverse(updated))
+        }),
+        PostSort::Title => posts.sort_by_cached_key(|post| post.title.to_lowercase()),
+    }
+}
+
+/// Fetch a post by ID, or by slug from the posts outside any space or in `space`.
+fn find_post(client: &MoynClient, post: &str, space: Option<&str>) -> Result<Post, Error> {
+    if let Ok(id) = post.parse::<u64>() {
+        return client.get_post(id);
+    }
+
+    for found in client.posts(space) {
+        let found = found?;
+        if found.slug == post {
+            // Listings may leave out the body, so fetch the post itself
+            return client.get_post(found.id);
+        }
+    }
+
+    Err(Error::NotFound(match space {
+        Some(space) => format!("No post with slug '{}' in space '{}'.", post, space),
+        None => format!("No post with slug '{}' outside of spaces. Use --space to look in a space.", post),
+    }))
+}
+
+fn post_show(client: &MoynClient, post: String, space: Option<String>, body: bool, output: OutputFormat) -> Result<(), Error> {
+    let mut post = find_post(client, &post, space.as_deref())?;
+    if !body {
+        post.content = None;
+    }
+    if output != OutputFormat::Table {
+        return print_item(output, &post);
+    }
+
+    println!("Post: {}", post.title);
+    println!("  ID:        {}", post.id);
+    println!("  Slug:      {}", post.slug);
+    println!("  URL:       {}", post.url);
+    println!("  Space:     {}", post.space.as_deref().unwrap_or("(profile)"));
+    println!("  Status:    {}", if is_published(&post) { "published" } else { "draft" });
+    if !post.tags.is_empty() {
+        println!("  Tags:      {}", post.tags.join(", "));
+    }
+    for (label, time) in [("Published", &post.published_at), ("Created", &post.created_at), ("Updated", &post.updated_at)] {
+        if let Some(time) = time {
+            println!("  {:<10} {}", format!("{}:", label), time);
+        }
+    }
+
+    if let Some(content) = &post.content {
+        println!();
+        print!("{}", content);
+        if !content.ends_with('\n') {
+            println!();
+        }
+    }
+    Ok(())
+}
+
+/// Shorten `s` to at most `max` characters, ending in `...` when cut.
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().nth(max).is_none() {
+        return s.to_string();
+    }
+    let end = s.char_indices().nth(max - 3).map_or(s.len(), |(i, _)| i);
+    format!("{}...", &s[..end])
+}
+
+/// Recreate a post in another space or on the profile, keeping its slug and
+/// tags, and delete the original unless `keep_original` is set.
+fn post_transfer(
+    client: &MoynClient,
+    id: u64,
+    to: PostDestination,
+    keep_original: bool,
+    output: OutputFormat,
+) -> Result<(), Error> {
+    let space = to.to_space;
+    let place = match &space {
+        Some(space) => format!("space '{}'", space),
+        None => "your profile".to_string(),
+    };
+
+    let post = client.get_post(id)?;
+    if !keep_original && post.space == space {
+        return Err(Error::Validation(format!("Post {} is already on {}.", id, place)));
+    }
+    let content = post
+        .content
+        .clone()
+        .ok_or_else(|| Error::Server(format!("The server did not send the content of post {}.", id)))?;
+
+    let mut request = CreatePost {
+        title: post.title.clone(),
+        content,
+        published: is_published(&post),
+        slug: Some(post.slug.clone()),
+        tags: Some(post.tags.clone()),
+    };
+    let created = match client.create_post(space.as_deref(), &request) {
+        Err(Error::Validation(reason)) if reason.to_lowercase().contains("slug") => {
+            eprintln!("Warning: could not keep the slug '{}' ({}), so the server picks a new one.", post.slug, reason);
+            request.slug = None;
+            client.create_post(space.as_deref(), &request)
+        }
+        result => result,
+    }
+    .map_err(|e| e.context(format!("Failed to create the post on {}", place)))?;
+
+    let mut updated_files = Vec::new();
+    if !keep_original {
+        client.delete_post(id).map_err(|e| {
+            e.context(format!("Created post {} on {}, but could not delete the original post {}", created.id, place, id))
+        })?;
+        updated_files = update_moved_files(client.config(), id, &created, space.as_deref())?;
+    }
+
+    if output != OutputFormat::Table {
+        return print_item(output, &created);
+    }
 
-    println!("{:<20} {:<30} {:<10} {}", "SLUG", "NAME", "VISIBILITY", "URL");
-    println!("{}", "-".repeat(75));
-    for space in spaces_response.spaces {
+    let verb = if keep_original { "Copied" } else { "Moved" };
+    println!("{} post {} to {} as post {}.", verb, id, place, created.id);
+    println!("URL: {}", created.url);
+    if let Some(published_at) = &post.published_at
+        && created.published_at.as_ref() != Some(published_at)
+    {
         println!(
-            "{:<20} {:<30} {:<10} {}",
-            truncate(&space.slug, 18),
-            truncate(&space.name, 28),
-            space.visibility,
-            space.url
+            "The new post's publication date is {} (the original's was {}).",
+            created.published_at.as_deref().unwrap_or("unset"),
+            published_at
         );
     }
+    for file in updated_files {
+        println!("Updated: {}", file.display());
+    }
+    Ok(())
+}
+
+/// Point the local files recorded for a moved post at its new ID and space.
+/// Returns the files whose frontmatter was changed.
+fn update_moved_files(config: &Config, old_id: u64, post: &Post, space: Option<&str>) -> Result<Vec<PathBuf>, Error> {
+    let mut state = load_state(config)?;
+    let mut updated = Vec::new();
+
+    for (key, published) in state.posts.iter_mut().filter(|(_, published)| published.id == old_id) {
+        published.id = post.id;
+        // The space is part of the hash, so the next sync sends the file again either way
+        published.hash = None;
+
+        let file = PathBuf::from(key);
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let frontmatter = parse_frontmatter(&content, true).ok().map(|parsed| parsed.frontmatter);
+
+        let mut fields = Vec::new();
+        if let Some(space) = space {
+            fields.push(("space", yaml_scalar(space)));
+        }
+        if frontmatter.as_ref().is_some_and(|f| f.id.is_some()) {
+            fields.push(("id", post.id.to_string()));
+        }
+        if frontmatter.as_ref().is_some_and(|f| f.url.is_some()) {
+            fields.push(("url", yaml_scalar(&post.url)));
+        }
+        // The new post has its own publication date
+        let has_published_at = frontmatter.as_ref().is_some_and(|f| f.published_at.is_some());
+        if has_published_at && let Some(published_at) = &post.published_at {
+            fields.push(("published_at", yaml_scalar(published_at)));
+        }
+
+        let mut changed = content.clone();
+        if !fields.is_empty() {
+            changed = set_frontmatter_fields(&changed, &fields);
+        }
+        let mut stale = Vec::new();
+        if space.is_none() {
+            stale.push("space");
+        }
+        if has_published_at && post.published_at.is_none() {
+            stale.push("published_at");
+        }
+        changed = remove_frontmatter_fields(&changed, &stale);
+        if changed != content {
+            fs::write(&file, changed).map_err(|e| format!("Could not update {}: {}", file.display(), e))?;
+            updated.push(file);
+        }
+    }
+
+    save_state(config, &state)?;
+    Ok(updated)
+}
+
+fn delete(client: &MoynClient, id: u64) -> Result<(), Error> {
+    client.delete_post(id)?;
+    println!("Post {} deleted.", id);
     Ok(())
 }
 
+fn spaces(client: &MoynClient, list: ListArgs, output: OutputFormat) -> Result<(), Error> {
+    print_list(output, client.spaces(), list.limit(output))
+}
+
 fn space_create(
+    client: &MoynClient,
     name: String,
     slug: Option<String>,
     description: Option<String>,
     visibility: String,
-) -> Result<(), String> {
-    let config = load_config()?;
+    output: OutputFormat,
+) -> Result<(), Error> {
+    validate_visibility(&visibility)?;
+
+    let request = CreateSpace {
+        slug,
+        name: Some(name),
+        description,
+        visibility: Some(visibility),
+    };
+
+    let space = client
+        .create_space(&request)
+        .map_err(|e| e.context("Failed to create space"))?;
+    if output != OutputFormat::Table {
+        return print_item(output, &space);
+    }
+
+    println!("Created space: {}", space.name);
+    println!("URL: {}", space.url);
+
+    if let Some(token_url) = space.token_url {
+        println!("Share URL: {}", token_url);
+    }
+
+    println!("\nPublish to this space:");
+    println!("  Add `space: {}` to your markdown frontmatter", space.slug);
+
+    Ok(())
+}
 
-    // Validate visibility
-    if !["public", "unlisted", "private"].contains(&visibility.as_str()) {
-        return Err(format!(
+fn validate_visibility(visibility: &str) -> Result<(), Error> {
+    if !["public", "unlisted", "private"].contains(&visibility) {
+        return Err(Error::Validation(format!(
             "Invalid visibility '{}'. Must be one of: public, unlisted, private",
             visibility
+        )));
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question. Without a terminal to ask on, fail instead of
+/// guessing, pointing at `--yes`.
+fn confirm(question: &str) -> Result<bool, Error> {
+    if !io::stdin().is_terminal() {
+        return Err(Error::Validation(format!("{} Pass --yes to confirm when not running interactively.", question)));
+    }
+
+    print!("{} [y/N] ", question);
+    io::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| format!("Could not read input: {}", e))?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+fn space_update(
+    client: &MoynClient,
+    slug: String,
+    name: Option<String>,
+    description: Option<String>,
+    visibility: Option<String>,
+    output: OutputFormat,
+) -> Result<(), Error> {
+    if name.is_none() && description.is_none() && visibility.is_none() {
+        return Err(Error::Validation(
+            "Nothing to update. Pass --name, --description or --visibility.".to_string(),
         ));
     }
+    if let Some(visibility) = &visibility {
+        validate_visibility(visibility)?;
+    }
 
-    let request = CreateSpaceRequest {
-        space: CreateSpace {
-            slug,
-            name,
-            description,
-            visibility: Some(visibility),
-        },
+    let changes = CreateSpace {
+        name,
+        description,
+        visibility,
+        ..CreateSpace::default()
     };
+    let space = client
+        .update_space(&slug, &changes)
+        .map_err(|e| e.context("Failed to update space"))?;
+    if output != OutputFormat::Table {
+        return print_item(output, &space);
+    }
+
+    println!("Updated space: {}", space.name);
+    println!("  Visibility: {}", space.visibility);
+    println!("  URL:        {}", space.url);
+    Ok(())
+}
 
-    let response = client(&config)
-        .post(format!("{}/api/v1/spaces", config.api_url))
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .json(&request)
-        .send()
-        .map_err(|e| format!("Request failed: {}", e))?;
+fn space_rename(client: &MoynClient, slug: String, new_slug: String, yes: bool, output: OutputFormat) -> Result<(), Error> {
+    let space = client.get_space(&slug)?;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().unwrap_or_default();
-        return Err(format!("Failed to create space: {} - {}", status, body));
+    if !yes {
+        eprintln!("Renaming changes the URL of '{}'. Links to {} will stop working,", space.name, space.url);
+        eprintln!("and so will share URLs handed out for it.");
+        eprintln!("Local files with `space: {}` need to be updated to `space: {}`.", slug, new_slug);
+        if !confirm(&format!("Rename space '{}' to '{}'?", slug, new_slug))? {
+            println!("Nothing was changed.");
+            return Ok(());
+        }
     }
 
-    let space_response: SpaceResponse = response
-        .json()
-        .map_err(|e| format!("Could not parse response: {}", e))?;
+    let changes = CreateSpace {
+        slug: Some(new_slug),
+        ..CreateSpace::default()
+    };
+    let space = client
+        .update_space(&slug, &changes)
+        .map_err(|e| e.context("Failed to rename space"))?;
+    if output != OutputFormat::Table {
+        return print_item(output, &space);
+    }
 
-    let space = space_response.space;
-    println!("Created space: {}", space.name);
+    println!("Renamed space '{}' to '{}'.", slug, space.slug);
     println!("URL: {}", space.url);
-
     if let Some(token_url) = space.token_url {
         println!("Share URL: {}", token_url);
     }
+    Ok(())
+}
 
-    println!("\nPublish to this space:");
-    println!("  Add `space: {}` to your markdown frontmatter", space.slug);
+fn space_delete(client: &MoynClient, slug: String, yes: bool) -> Result<(), Error> {
+    let space = client.get_space(&slug)?;
 
+    if !yes && !confirm(&format!("Delete space '{}' ({})? This cannot be undone.", space.name, space.slug))? {
+        println!("Nothing was deleted.");
+        return Ok(());
+    }
+
+    client.delete_space(&slug)?;
+    println!("Space '{}' deleted.", slug);
     Ok(())
 }
 
-fn space_show(slug: String) -> Result<(), String> {
-    let config = load_config()?;
+fn space_members(client: &MoynClient, slug: String, list: ListArgs, output: OutputFormat) -> Result<(), Error> {
+    print_list(output, client.members(&slug), list.limit(output))
+}
 
-    let response = client(&config)
-        .get(format!("{}/api/v1/spaces/{}", config.api_url, slug))
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .send()
-        .map_err(|e| format!("Request failed: {}", e))?;
+fn space_invite(client: &MoynClient, slug: String, member: String, role: String, output: OutputFormat) -> Result<(), Error> {
+    let invite = if member.contains('@') && !member.starts_with('@') {
+        Invite { username: None, email: Some(member), role }
+    } else {
+        Invite { username: Some(member.trim_start_matches('@').to_string()), email: None, role }
+    };
 
-    if response.status().as_u16() == 404 {
-        return Err(format!("Space '{}' not found or you don't have access.", slug));
+    let member = client
+        .invite_member(&slug, &invite)
+        .map_err(|e| e.context("Failed to add member"))?;
+    if output != OutputFormat::Table {
+        return print_item(output, &member);
     }
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().unwrap_or_default();
-        return Err(format!("Failed to fetch space: {} - {}", status, body));
+    if member.status.as_deref() == Some("invited") {
+        println!("Invited {} to '{}' as {}.", member_label(&member), slug, member.role);
+    } else {
+        println!("Added {} to '{}' as {}.", member_label(&member), slug, member.role);
     }
+    Ok(())
+}
 
-    let space_response: SpaceResponse = response
-        .json()
-        .map_err(|e| format!("Could not parse response: {}", e))?;
+fn space_remove_member(client: &MoynClient, slug: String, member: String) -> Result<(), Error> {
+    client.remove_member(&slug, member.trim_start_matches('@'))?;
+    println!("Removed {} from '{}'.", member, slug);
+    Ok(())
+}
 
-    let space = space_response.space;
+fn space_show(client: &MoynClient, slug: String, output: OutputFormat) -> Result<(), Error> {
+    let space = client.get_space(&slug)?;
+    if output != OutputFormat::Table {
+        return print_item(output, &space);
+    }
 
     println!("Space: {}", space.name);
     println!("  Slug:       {}", space.slug);
     println!("  Visibility: {}", space.visibility);
-    if let Some(desc) = &space.description {
-        if !desc.is_empty() {
-            println!("  Description: {}", desc);
-        }
+    if let Some(desc) = &space.description
+        && !desc.is_empty()
+    {
+        println!("  Description: {}", desc);
     }
     println!("  URL:        {}", space.url);
 
@@ -536,28 +2932,438 @@ fn space_show(slug: String) -> Result<(), String> {
         println!("  Access Token: {}", token);
     }
 
+    if let Some(expires_at) = &space.token_expires_at {
+        println!("  Expires:    {}", expires_at);
+    }
+
     Ok(())
 }
 
-fn main() {
-    let cli = Cli::parse();
+/// Parse a duration like `30m`, `12h`, `7d` or `2w`.
+fn parse_duration(value: &str) -> Result<chrono::Duration, Error> {
+    let invalid = || Error::Validation(format!("Invalid duration '{}'. Use a number and a unit, e.g. 30m, 12h, 7d or 2w.", value));
+
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    if amount == 0 {
+        return Err(invalid());
+    }
+
+    let duration = match unit {
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => None,
+    };
+    duration.ok_or_else(invalid)
+}
+
+fn space_token_rotate(
+    client: &MoynClient,
+    slug: String,
+    expires: Option<String>,
+    yes: bool,
+    output: OutputFormat,
+) -> Result<(), Error> {
+    let expires_at = expires
+        .as_deref()
+        .map(parse_duration)
+        .transpose()?
+        .map(|duration| (chrono::Utc::now() + duration).to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+
+    let space = client.get_space(&slug)?;
+    if !yes {
+        if let Some(token_url) = &space.token_url {
+            eprintln!("The current share URL of '{}' will stop working:", space.name);
+            eprintln!("  {}", token_url);
+        }
+        if !confirm(&format!("Issue a new share URL for '{}'?", slug))? {
+            println!("Nothing was changed.");
+            return Ok(());
+        }
+    }
+
+    let space = client
+        .rotate_space_token(&slug, expires_at.as_deref())
+        .map_err(|e| e.context("Failed to rotate access token"))?;
+    if output != OutputFormat::Table {
+        return print_item(output, &space);
+    }
+
+    println!("Issued a new access token for '{}'.", space.slug);
+    if let Some(token_url) = &space.token_url {
+        println!("Share URL: {}", token_url);
+    }
+    if let Some(expires_at) = &space.token_expires_at {
+        println!("Expires:   {}", expires_at);
+    }
+    Ok(())
+}
+
+fn space_token_revoke(client: &MoynClient, slug: String, yes: bool) -> Result<(), Error> {
+    let space = client.get_space(&slug)?;
+    if space.token_url.is_none() && space.access_token.is_none() {
+        println!("'{}' has no share URL.", slug);
+        return Ok(());
+    }
+
+    if !yes {
+        if let Some(token_url) = &space.token_url {
+            eprintln!("The share URL of '{}' will stop working:", space.name);
+            eprintln!("  {}", token_url);
+        }
+        if !confirm(&format!("Revoke the access token of '{}'?", slug))? {
+            println!("Nothing was changed.");
+            return Ok(());
+        }
+    }
+
+    client
+        .revoke_space_token(&slug)
+        .map_err(|e| e.context("Failed to revoke access token"))?;
+    println!("Revoked the access token of '{}'. Its share URL no longer works.", slug);
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    let profile = cli.profile.as_deref();
+
+    // Scripts asking for JSON must not get text they can't parse
+    if cli.output != OutputFormat::Table
+        && let Some(command) = cli.command.text_only()
+    {
+        let format = cli.output.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+        return Err(Error::Validation(format!(
+            "`moyn {}` only prints text and doesn't support --output {}.",
+            command, format
+        )));
+    }
 
-    let result = match cli.command {
-        Commands::Login => login(),
-        Commands::Publish { file } => publish(file),
-        Commands::Posts => posts(),
-        Commands::Delete { id } => delete(id),
-        Commands::Spaces => spaces(),
+    match cli.command {
+        Commands::Login { token_stdin, url, no_keyring } => login(profile, token_stdin, url, no_keyring),
+        Commands::Whoami => whoami(&connect(profile)?, cli.output),
+        Commands::Profile { command } => match command {
+            ProfileCommands::List => profile_list(),
+            ProfileCommands::Use { name } => profile_use(name),
+            ProfileCommands::Remove { name } => profile_remove(name),
+        },
+        Commands::New(args) => new_post(args, profile),
+        Commands::Publish(args) if args.dry_run => publish_dry_run(&load_offline_config(profile)?, args),
+        Commands::Publish(args) => publish(&connect(profile)?, args, cli.output),
+        Commands::Sync { dir, delete, dry_run, content } => sync(&connect(profile)?, dir, delete, dry_run, content),
+        Commands::Pull { dir, space, force } => pull(&connect(profile)?, dir, space, force),
+        Commands::Posts(args) => posts(&connect(profile)?, args, cli.output),
+        Commands::Post { command } => match command {
+            PostCommands::Show { post, space, body } => post_show(&connect(profile)?, post, space, body, cli.output),
+            PostCommands::Move { id, to } => post_transfer(&connect(profile)?, id, to, false, cli.output),
+            PostCommands::Copy { id, to } => post_transfer(&connect(profile)?, id, to, true, cli.output),
+        },
+        Commands::Delete { id } => delete(&connect(profile)?, id),
+        Commands::Spaces(list) => spaces(&connect(profile)?, list, cli.output),
         Commands::Space { command } => match command {
             SpaceCommands::Create { name, slug, description, visibility } => {
-                space_create(name, slug, description, visibility)
+                space_create(&connect(profile)?, name, slug, description, visibility, cli.output)
+            }
+            SpaceCommands::Show { slug } => space_show(&connect(profile)?, slug, cli.output),
+            SpaceCommands::Update { slug, name, description, visibility } => {
+                space_update(&connect(profile)?, slug, name, description, visibility, cli.output)
+            }
+            SpaceCommands::Rename { slug, new_slug, yes } => {
+                space_rename(&connect(profile)?, slug, new_slug, yes, cli.output)
             }
-            SpaceCommands::Show { slug } => space_show(slug),
+            SpaceCommands::Delete { slug, yes } => space_delete(&connect(profile)?, slug, yes),
+            SpaceCommands::Members { slug, list } => space_members(&connect(profile)?, slug, list, cli.output),
+            SpaceCommands::Invite { slug, member, role } => {
+                space_invite(&connect(profile)?, slug, member, role, cli.output)
+            }
+            SpaceCommands::RemoveMember { slug, member } => space_remove_member(&connect(profile)?, slug, member),
+            SpaceCommands::Token { command } => match command {
+                TokenCommands::Rotate { slug, expires, yes } => {
+                    space_token_rotate(&connect(profile)?, slug, expires, yes, cli.output)
+                }
+                TokenCommands::Revoke { slug, yes } => space_token_revoke(&connect(profile)?, slug, yes),
+            },
         },
-    };
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
 
-    if let Err(e) = result {
+    if let Err(e) = run(cli) {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        std::process::exit(e.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn text_only_commands_reject_output_formats() {
+        let cli = Cli::try_parse_from(["moyn", "-o", "json", "sync", "posts/"]).unwrap();
+        assert!(matches!(run(cli), Err(Error::Validation(_))));
+
+        let cli = Cli::try_parse_from(["moyn", "-o", "json", "publish", "--dry-run", "post.md"]).unwrap();
+        assert!(matches!(run(cli), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn only_tables_are_cut_by_default() {
+        let list = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["moyn", "spaces"].iter().chain(args)).unwrap();
+            match cli.command {
+                Commands::Spaces(list) => list,
+                _ => unreachable!(),
+            }
+        };
+
+        assert_eq!(list(&[]).limit(OutputFormat::Table), Some(DEFAULT_LIST_LIMIT));
+        assert_eq!(list(&[]).limit(OutputFormat::Json), None);
+        assert_eq!(list(&["-n", "5"]).limit(OutputFormat::Tsv), Some(5));
+        assert_eq!(list(&["--all"]).limit(OutputFormat::Table), None);
+    }
+
+    #[test]
+    fn parses_frontmatter_and_strips_it_from_content() {
+        let parsed = parse_frontmatter("---\ntitle: Hello\ntags: [rust, cli]\nspace: journal\n---\n\nBody text\n", false).unwrap();
+
+        assert_eq!(parsed.frontmatter.title.as_deref(), Some("Hello"));
+        assert_eq!(parsed.frontmatter.tags, Some(vec!["rust".to_string(), "cli".to_string()]));
+        assert_eq!(parsed.frontmatter.space.as_deref(), Some("journal"));
+        assert_eq!(parsed.content, "Body text\n");
+    }
+
+    #[test]
+    fn content_without_frontmatter_is_unchanged() {
+        let parsed = parse_frontmatter("# Title\n\nBody\n", false).unwrap();
+
+        assert!(parsed.frontmatter.title.is_none());
+        assert_eq!(parsed.content, "# Title\n\nBody\n");
+    }
+
+    #[test]
+    fn parses_crlf_line_endings() {
+        let parsed = parse_frontmatter("---\r\ntitle: Hello\r\npublished: false\r\n---\r\n\r\nBody\r\n", false).unwrap();
+
+        assert_eq!(parsed.frontmatter.title.as_deref(), Some("Hello"));
+        assert_eq!(parsed.frontmatter.published, Some(false));
+        assert_eq!(parsed.content, "Body\r\n");
+    }
+
+    #[test]
+    fn skips_leading_bom() {
+        let parsed = parse_frontmatter("\u{feff}---\ntitle: Hello\n---\nBody\n", false).unwrap();
+
+        assert_eq!(parsed.frontmatter.title.as_deref(), Some("Hello"));
+        assert_eq!(parsed.content, "Body\n");
+    }
+
+    #[test]
+    fn keeps_dashes_inside_code_blocks() {
+        let content = "---\ntitle: Hello\n---\n\n```yaml\n---\nkey: value\n---\n```\n";
+        let parsed = parse_frontmatter(content, false).unwrap();
+
+        assert_eq!(parsed.frontmatter.title.as_deref(), Some("Hello"));
+        assert_eq!(parsed.content, "```yaml\n---\nkey: value\n---\n```\n");
+    }
+
+    #[test]
+    fn closing_marker_must_be_a_whole_line() {
+        let parsed = parse_frontmatter("---\ntitle: Hello\n---\n----\n", false).unwrap();
+
+        assert_eq!(parsed.frontmatter.title.as_deref(), Some("Hello"));
+        assert_eq!(parsed.content, "----\n");
+    }
+
+    #[test]
+    fn parses_empty_frontmatter() {
+        let parsed = parse_frontmatter("---\n---\nBody\n", false).unwrap();
+
+        assert!(parsed.frontmatter.title.is_none());
+        assert_eq!(parsed.content, "Body\n");
+    }
+
+    #[test]
+    fn rejects_unclosed_frontmatter() {
+        let error = parse_frontmatter("\n---\ntitle: Hello\nBody\n", false).unwrap_err();
+
+        assert_eq!((error.line, error.column), (2, 1));
+        assert!(error.message.contains("not closed"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_with_suggestion() {
+        let error = parse_frontmatter("---\ntitle: Hello\npubished: false\n---\nBody\n", false).unwrap_err();
+
+        assert_eq!((error.line, error.column), (3, 1));
+        assert_eq!(error.message, "unknown frontmatter key `pubished` (did you mean `published`?)");
+        assert_eq!(
+            error.at(Path::new("post.md")),
+            "post.md:3:1: unknown frontmatter key `pubished` (did you mean `published`?)"
+        );
+    }
+
+    #[test]
+    fn reports_position_of_invalid_values() {
+        let error = parse_frontmatter("---\ntitle: Hello\npublished: maybe\n---\n", false).unwrap_err();
+
+        assert_eq!((error.line, error.column), (3, 12));
+        assert!(error.message.contains("expected a boolean"), "{}", error.message);
+    }
+
+    #[test]
+    fn lenient_mode_skips_unknown_keys() {
+        let parsed = parse_frontmatter("---\ntitle: Hello\nauthor: me\npublished: false\n---\nBody\n", true).unwrap();
+
+        assert_eq!(parsed.frontmatter.title.as_deref(), Some("Hello"));
+        assert_eq!(parsed.frontmatter.published, Some(false));
+        assert_eq!(parsed.content, "Body\n");
+        assert_eq!(parsed.warnings.len(), 1);
+        assert_eq!(parsed.warnings[0].line, 3);
+        assert!(parsed.warnings[0].message.starts_with("unknown frontmatter key `author`, expected one of"));
+    }
+
+    #[test]
+    fn lenient_mode_ignores_invalid_yaml() {
+        let parsed = parse_frontmatter("---\ntitle: [unclosed\n---\nBody\n", true).unwrap();
+
+        assert!(parsed.frontmatter.title.is_none());
+        assert_eq!(parsed.content, "Body\n");
+        assert_eq!(parsed.warnings.len(), 1);
+    }
+
+    #[test]
+    fn finds_link_targets_outside_code_blocks() {
+        let content = "![diagram](./diagram.png \"Diagram\")\n\
+            See [the spec](<docs/spec file.pdf>) and [site](https://moyn.dev).\n\
+            <img src=\"img/logo.svg\" alt=\"\">\n\
+            ```md\n![not this](skip.png)\n```\n";
+
+        let targets: Vec<&str> = link_targets(content).into_iter().map(|range| &content[range]).collect();
+
+        assert_eq!(targets, ["./diagram.png", "docs/spec file.pdf", "https://moyn.dev", "img/logo.svg"]);
+    }
+
+    #[test]
+    fn tsv_escape_keeps_cells_on_one_line() {
+        assert_eq!(tsv_escape("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    #[test]
+    fn state_belongs_to_one_instance() {
+        let state = claim_state(State::default(), "https://moyn.dev/").unwrap();
+        assert_eq!(state.api_url.as_deref(), Some("https://moyn.dev"));
+
+        let state = claim_state(state, "https://moyn.dev").unwrap();
+        assert_eq!(claim_state(state, "https://staging.moyn.dev").err().as_deref(), Some("https://moyn.dev"));
+    }
+
+    #[test]
+    fn follow_rename_moves_the_state_of_a_removed_file() {
+        let mut state = State::default();
+        let old = "/nonexistent/posts/old.md";
+        state.posts.insert(old.to_string(), PublishedFile { id: 7, hash: Some("abc".to_string()) });
+
+        // Another post, or a file outside the synced directory, is no rename
+        assert_eq!(follow_rename(&mut state, "/nonexistent/posts", "/nonexistent/posts/new.md", 8), None);
+        assert_eq!(follow_rename(&mut state, "/nonexistent/other", "/nonexistent/other/new.md", 7), None);
+
+        let new = "/nonexistent/posts/new.md";
+        assert_eq!(follow_rename(&mut state, "/nonexistent/posts", new, 7).as_deref(), Some(old));
+        assert!(!state.posts.contains_key(old));
+        assert_eq!(state.posts[new].id, 7);
+        assert_eq!(state.posts[new].hash.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn truncate_cuts_on_character_boundaries() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("Zoë Ångström-Müller", 10), "Zoë Ång...");
+        assert_eq!(truncate("ééééé", 5), "ééééé");
+    }
+
+    #[test]
+    fn slugify_joins_words_with_hyphens() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust 2024 -- what's new?  "), "rust-2024-what-s-new");
+    }
+
+    #[test]
+    fn set_frontmatter_fields_keeps_key_order() {
+        let content = "---\ntitle: Hello\nid: 1\ntags:\n  - a\n---\nBody\n";
+        let updated = set_frontmatter_fields(content, &[("id", "42".to_string()), ("url", "https://moyn.dev/p/hello".to_string())]);
+
+        assert_eq!(updated, "---\ntitle: Hello\nid: 42\ntags:\n  - a\nurl: https://moyn.dev/p/hello\n---\nBody\n");
+    }
+
+    #[test]
+    fn post_filter_matches_tags_state_and_dates() {
+        let post: Post = serde_json::from_value(serde_json::json!({
+            "id": 1, "title": "Hello", "slug": "hello", "url": "https://moyn.dev/p/hello",
+            "tags": ["Rust", "cli"], "published": true, "published_at": "2026-03-01T10:00:00Z",
+        }))
+        .unwrap();
+        let date = |d: &str| d.parse::<chrono::NaiveDate>().ok();
+
+        assert!(PostFilter::default().matches(&post));
+        assert!(PostFilter { tags: vec!["rust".into(), "cli".into()], published: true, ..Default::default() }.matches(&post));
+        assert!(!PostFilter { tags: vec!["go".into()], ..Default::default() }.matches(&post));
+        assert!(!PostFilter { drafts: true, ..Default::default() }.matches(&post));
+        assert!(PostFilter { since: date("2026-03-01"), until: date("2026-03-01"), ..Default::default() }.matches(&post));
+        assert!(!PostFilter { since: date("2026-03-02"), ..Default::default() }.matches(&post));
+    }
+
+    #[test]
+    fn parse_duration_accepts_common_units() {
+        assert_eq!(parse_duration("30m").unwrap(), chrono::Duration::minutes(30));
+        assert_eq!(parse_duration("7d").unwrap(), chrono::Duration::days(7));
+        assert_eq!(parse_duration("2w").unwrap(), chrono::Duration::weeks(2));
+        for invalid in ["", "7", "d", "0d", "7 days", "-1h"] {
+            assert!(parse_duration(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn remove_frontmatter_fields_drops_keys_and_their_lines() {
+        let content = "---\ntitle: Hello\nspace: journal\ntags:\n  - a\nid: 1\n---\nspace: body\n";
+
+        assert_eq!(remove_frontmatter_fields(content, &["space", "tags"]), "---\ntitle: Hello\nid: 1\n---\nspace: body\n");
+        assert_eq!(remove_frontmatter_fields("# Hello\n", &["space"]), "# Hello\n");
+    }
+
+    #[test]
+    fn render_template_quotes_values_in_frontmatter() {
+        let template = "---\ntitle: {{title}}\ntags: [til]\n---\n# {{title}}\n";
+        let rendered = render_template(template, &[("title", "Rust: again")]);
+
+        assert_eq!(rendered, "---\ntitle: 'Rust: again'\ntags: [til]\n---\n# Rust: again\n");
+        assert_eq!(parse_frontmatter(&rendered, false).unwrap().frontmatter.title.as_deref(), Some("Rust: again"));
+    }
+
+    #[test]
+    fn unsafe_slugs_are_not_file_names() {
+        assert!(is_safe_file_name("hello-world"));
+        for slug in ["", "..", "../etc/passwd", "a/b", "a\\b", ".hidden", "a..b"] {
+            assert!(!is_safe_file_name(slug), "{slug}");
+        }
+    }
+
+    #[test]
+    fn set_frontmatter_fields_adds_missing_block() {
+        let updated = set_frontmatter_fields("# Hello\n", &[("id", "42".to_string())]);
+
+        assert_eq!(updated, "---\nid: 42\n---\n\n# Hello\n");
+        assert_eq!(parse_frontmatter(&updated, false).unwrap().frontmatter.id, Some(42));
     }
 }